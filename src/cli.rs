@@ -2,6 +2,12 @@ use std::path::PathBuf;
 
 use clap::{Args, Parser, Subcommand};
 
+use crate::core::{
+    catalog::{OutputFormat, SortKey},
+    clone::ConflictPolicy,
+    utils::SymlinkPolicy,
+};
+
 #[derive(Parser)]
 #[command(version, about, long_about = None, arg_required_else_help = true)]
 pub struct Cli {
@@ -27,6 +33,57 @@ pub enum Commands {
         dir: Option<String>,
     },
 
+    /// Re-apply the latest version of a snapshot or git repository to a project cloned from it
+    Update {
+        /// Project to update, the current folder when not set
+        #[arg(short, long)]
+        dir: Option<String>,
+
+        /// Use the default of new variables instead of prompting for them
+        #[arg(long)]
+        defaults: bool,
+    },
+
+    /// Remove stored files no snapshot refers to anymore
+    Gc,
+
+    /// Search the snapshots of every registry
+    #[command(arg_required_else_help = true)]
+    Search {
+        /// Part of the id, description or a tag
+        term: String,
+    },
+
+    /// Add a snapshot of a registry to the local snapshots
+    #[command(arg_required_else_help = true)]
+    Pull {
+        /// Snapshot to pull, as registry/id
+        reference: String,
+
+        /// Replace the local snapshot with the same id
+        #[arg(long)]
+        force: bool,
+
+        /// Pull the snapshot with another id
+        #[arg(long = "as", value_name = "ID")]
+        new_id: Option<String>,
+
+        /// Accept symlinks pointing outside the snapshot
+        #[arg(long)]
+        allow_outside_symlinks: bool,
+    },
+
+    /// Share the latest version of a snapshot in a registry
+    #[command(arg_required_else_help = true)]
+    Publish {
+        /// Select snapshot
+        snapshot_id: String,
+
+        /// Registry to publish to, needed when more than one is configured
+        #[arg(short, long)]
+        registry: Option<String>,
+    },
+
     #[cfg(feature = "tui")]
     /// Run terminal-user-interface
     Tui {},
@@ -62,231 +119,480 @@ pub enum SnapshotOptions {
         /// If set will create a name for the snapshot, if not will create an unique ID
         #[arg(short, long)]
         name: Option<String>,
+
+        /// What to do with symlinks pointing outside the project
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t)]
+        outside_symlinks: SymlinkPolicy,
+
+        /// Keep the modification time of every file
+        #[arg(long)]
+        preserve_mtime: bool,
+
+        /// Also leave out what the project's .gitignore files and .git/info/exclude ignore
+        #[arg(long, conflicts_with = "git_tracked_only")]
+        respect_gitignore: bool,
+
+        /// Only copy the files tracked in the project's git index
+        #[arg(long)]
+        git_tracked_only: bool,
+
+        /// Describe what the snapshot is for, instead of the description in setuprs.toml
+        #[arg(long)]
+        description: Option<String>,
+
+        /// Author of the snapshot, instead of the author in setuprs.toml
+        #[arg(long)]
+        author: Option<String>,
+
+        /// Tag the snapshot, instead of the tags in setuprs.toml (repeatable)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
     },
 
     /// Clone snapshot
     #[command(arg_required_else_help = true)]
     Clone {
-        /// Select snapshot
+        /// Select snapshot, as id for the latest version or id@version, or clone directly
+        /// from a project folder or git repository
         snapshot_id: String,
 
         /// Define TO here setuprs should clone the snapshot
         #[arg(short, long)]
         destination_path: Option<String>,
+
+        /// Set the value of a variable instead of prompting for it (repeatable)
+        #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_key_value)]
+        set: Vec<(String, String)>,
+
+        /// Read variable values from a TOML or JSON file
+        #[arg(long, value_name = "FILE")]
+        answers: Option<PathBuf>,
+
+        /// Use the default of every variable not otherwise provided, never prompting
+        #[arg(long)]
+        defaults: bool,
+
+        /// What to do with symlinks pointing outside the snapshot
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t)]
+        outside_symlinks: SymlinkPolicy,
+
+        /// Keep the modification time of every file
+        #[arg(long)]
+        preserve_mtime: bool,
+
+        /// Branch, tag or commit to clone from a git repository
+        #[arg(long = "ref", value_name = "REF")]
+        git_ref: Option<String>,
+
+        /// Folder of the source holding the template
+        #[arg(long, value_name = "PATH")]
+        subdir: Option<String>,
+
+        /// Show the files the clone would create or overwrite, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// With --dry-run, also show how every overwritten file would change
+        #[arg(long, requires = "dry_run")]
+        diff: bool,
+
+        /// What to do with files of the destination the snapshot would change
+        #[arg(long, value_enum, value_name = "POLICY", default_value_t)]
+        on_conflict: ConflictPolicy,
     },
 
-    /// Show all snapshots_path
-    Show,
-}
+    /// List the snapshots with their description, tags, variables, size and last update
+    Show {
+        /// Only list snapshots with this tag (repeatable, all must match)
+        #[arg(long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
 
-// TODO: snapshots metadata
-#[cfg(test)]
-mod tests {
-    use std::{
-        fs::{self, File},
-        io::Write,
-        path::Path,
-        str::FromStr,
-    };
+        /// Only list snapshots whose id matches this glob
+        #[arg(long, value_name = "GLOB")]
+        filter: Option<String>,
 
-    use assert_cmd::Command;
-    use predicates::prelude::predicate;
-    use serial_test::serial;
-    use uuid::Uuid;
+        /// Column to sort the snapshots by
+        #[arg(long, value_enum, value_name = "KEY", default_value_t)]
+        sort: SortKey,
 
-    use crate::core::{utils::search_file_create_config_folder_if_not_found, Config};
+        /// Sort in descending order
+        #[arg(long)]
+        reverse: bool,
 
-    #[allow(dead_code)]
-    struct Noisy {
-        folder: String,
-        cleanup: Option<Box<dyn Fn()>>,
-    }
+        /// How to print the snapshots
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
 
-    struct NoisyFile<'a> {
-        name: &'a str,
-        content: &'a str,
-    }
+    /// Show the metadata and variables of a snapshot
+    #[command(arg_required_else_help = true)]
+    Info {
+        /// Select snapshot, as id or id@version
+        snapshot_id: String,
+    },
 
-    impl Noisy {
-        fn new() -> Self {
-            let uuid = Uuid::new_v4().to_string();
-            fs::create_dir(&uuid).unwrap();
-            Self {
-                folder: uuid,
-                cleanup: None,
-            }
-        }
+    /// List the versions of a snapshot
+    #[command(arg_required_else_help = true)]
+    Versions {
+        /// Select snapshot
+        snapshot_id: String,
+    },
 
-        fn folder(&self) -> String {
-            self.folder.clone()
-        }
+    /// Remove a snapshot with all its versions
+    #[command(arg_required_else_help = true)]
+    Delete {
+        /// Select snapshot
+        snapshot_id: String,
 
-        fn add_file(self, noisy_file: NoisyFile) -> Self {
-            let path = format!("./{}/{}", self.folder, noisy_file.name);
-            let mut file = File::create(path).unwrap();
-            file.write_all(noisy_file.content.as_bytes()).unwrap();
-            self
-        }
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 
-        fn add_snapshot_folder_config(self) -> Self {
-            let config = Config {
-                config_file_path: ".".to_string(),
-                debug_mode: "error".to_string(),
-                snapshots_path: format!("{}/snapshots/", self.folder()),
-            };
+    /// Change the id of a snapshot
+    #[command(arg_required_else_help = true)]
+    Rename {
+        /// Select snapshot
+        snapshot_id: String,
 
-            let file = "file.toml".to_string();
+        /// New id of the snapshot
+        new_id: String,
+    },
 
-            search_file_create_config_folder_if_not_found(
-                format!("./{}/{file}", self.folder).as_str(),
-                &config,
-            )
-            .unwrap();
-            self
-        }
+    /// Copy a snapshot with all its versions to another id
+    #[command(arg_required_else_help = true)]
+    Duplicate {
+        /// Select snapshot
+        snapshot_id: String,
 
-        fn add_config(self) -> Self {
-            let config = Config {
-                config_file_path: ".".to_string(),
-                debug_mode: "error".to_string(),
-                snapshots_path: ".".to_string(),
-            };
+        /// Id of the copy
+        new_id: String,
+    },
 
-            let file = "file.toml".to_string();
+    /// Pack a snapshot with all its versions into a .tar.gz archive
+    #[command(arg_required_else_help = true)]
+    Export {
+        /// Select snapshot
+        snapshot_id: String,
 
-            search_file_create_config_folder_if_not_found(
-                format!("./{}/{file}", self.folder).as_str(),
-                &config,
-            )
-            .unwrap();
-            self
-        }
+        /// Archive to write
+        #[arg(short, long, value_name = "FILE")]
+        output: String,
+    },
 
-        fn overwrite_cleanup(&mut self, closure: Box<dyn Fn()>) {
-            self.cleanup = Some(Box::new(closure));
-        }
+    /// Add the snapshot of an archive made by export
+    #[command(arg_required_else_help = true)]
+    Import {
+        /// Archive to read
+        archive: String,
 
-        fn add_folder(self, folder_name: &str) -> Self {
-            let path = format!("./{}/{}", self.folder, folder_name);
-            fs::create_dir(path).unwrap();
-            self
-        }
-    }
+        /// Replace the snapshot with the same id
+        #[arg(long)]
+        force: bool,
+
+        /// Import the snapshot with another id
+        #[arg(long = "as", value_name = "ID")]
+        new_id: Option<String>,
 
-    impl Drop for Noisy {
-        fn drop(&mut self) {
-            let _ = fs::remove_dir_all(format!("./{}", self.folder));
+        /// Accept symlinks pointing outside the snapshot
+        #[arg(long)]
+        allow_outside_symlinks: bool,
+    },
+}
 
-            match &self.cleanup {
-                Some(f) => f(),
-                None => {}
-            }
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
         }
+        _ => Err(format!("expected KEY=VALUE, got \"{s}\"")),
     }
+}
 
-    #[test]
-    #[serial]
-    fn on_snapshot_create_should_ignore_files_and_folders_on_setuprsignore() {
-        let noisy = &mut Noisy::new()
-            .add_config()
-            .add_file(NoisyFile {
-                name: "setuprs.toml",
-                content: "[project]\nname=\"project\"",
-            })
-            .add_file(NoisyFile {
-                name: ".setuprsignore",
-                content: "file1\nfolder1\nfolder2/file2",
-            })
-            .add_file(NoisyFile {
-                name: "file1",
-                content: "",
-            })
-            .add_folder("folder1")
-            .add_folder("folder2")
-            .add_file(NoisyFile {
-                name: "folder2/file2",
-                content: "",
-            });
-
-        let folder = noisy.folder.clone();
-
-        noisy.overwrite_cleanup(Box::new(move || {
-            fs::remove_dir_all("tag_name").unwrap();
-        }));
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, str::FromStr};
 
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
+    use assert_cmd::Command;
+    use predicates::prelude::{predicate, PredicateBooleanExt};
 
-        let on_folder = |file: &str| -> String { format!("./tag_name/{file}") };
+    use crate::core::{
+        store::{checkout, Tree},
+        testing::{serve, test_config, TestFolder},
+        Config,
+    };
 
-        cmd.arg("--config")
-            .arg(format!("./{folder}/file.toml"))
-            .arg("snapshot")
-            .arg("create")
-            .arg(format!("./{folder}"))
-            .arg("-n")
-            .arg("tag_name")
+    /// Checks out the latest version of the snapshot `id` of a folder using `with_config`
+    /// into `<folder>/<id>`
+    fn checkout_snapshot(folder: &TestFolder, id: &str) {
+        let config = test_config(folder.path());
+        let latest = *config.versions(id).unwrap().last().unwrap();
+
+        checkout(&config, id, latest, &folder.join(id), false).unwrap();
+    }
+
+    #[test]
+    fn on_snapshot_create_should_ignore_files_and_folders_on_setuprsignore() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_file("project/setuprs.toml", "[project]\nname=\"project\"")
+            .add_file("project/.setuprsignore", "file1\nfolder1\nfolder2/file2")
+            .add_file("project/file1", "")
+            .add_folder("project/folder1")
+            .add_file("project/folder2/file2", "");
+
+        let folder = test_folder.path().display().to_string();
+
+        let on_folder = |file: &str| -> String { format!("{folder}/tag_name/{file}") };
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "create",
+                &format!("./{folder}/project"),
+                "-n",
+                "tag_name",
+            ])
             .assert()
             .success()
             .stdout("tag_name\n");
 
+        checkout_snapshot(&test_folder, "tag_name");
+
         assert!(!Path::new(&on_folder("file1")).exists());
         assert!(!Path::new(&on_folder("folder1")).exists());
         assert!(Path::new(&on_folder("folder2")).exists());
         assert!(!Path::new(&on_folder("folder2/file2")).exists());
     }
 
+    #[test]
+    fn on_snapshot_create_should_respect_gitignore_or_only_copy_tracked_files() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_file("project/setuprs.toml", "[project]\nname=\"project\"")
+            .add_file("project/.gitignore", "target/\n.env")
+            .add_file("project/.env", "SECRET=1")
+            .add_file("project/notes.txt", "")
+            .add_file("project/target/app", "")
+            .add_file("project/src/main.rs", "");
+
+        let folder = test_folder.path().display().to_string();
+
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .args(args)
+                .current_dir(test_folder.join("project"))
+                .status()
+                .unwrap()
+        };
+        git(&["init", "-q"]);
+        git(&["add", ".gitignore", "src/main.rs"]);
+
+        for (flag, id) in [
+            ("--respect-gitignore", "ignored_snapshot"),
+            ("--git-tracked-only", "tracked_snapshot"),
+        ] {
+            test_folder
+                .setuprs(&[
+                    "snapshot",
+                    "create",
+                    &format!("./{folder}/project"),
+                    "-n",
+                    id,
+                    flag,
+                ])
+                .assert()
+                .success();
+        }
+
+        checkout_snapshot(&test_folder, "ignored_snapshot");
+        checkout_snapshot(&test_folder, "tracked_snapshot");
+
+        let exists = |file: &str| test_folder.join(file).exists();
+
+        assert!(exists("ignored_snapshot/src/main.rs"));
+        assert!(exists("ignored_snapshot/notes.txt"));
+        assert!(!exists("ignored_snapshot/.env"));
+        assert!(!exists("ignored_snapshot/target"));
+        assert!(!exists("ignored_snapshot/.git"));
+
+        assert!(exists("tracked_snapshot/src/main.rs"));
+        assert!(exists("tracked_snapshot/.gitignore"));
+        assert!(exists("tracked_snapshot/setuprs.toml"));
+        assert!(!exists("tracked_snapshot/notes.txt"));
+        assert!(!exists("tracked_snapshot/.env"));
+        assert!(!exists("tracked_snapshot/.git"));
+    }
+
+    #[test]
+    fn on_snapshot_info_should_show_manifest_and_variables() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_file(
+                "project/setuprs.toml",
+                "[project]
+name = \"info_snapshot\"
+author = \"Jane\"
+tags = [\"web\"]
+[[variables]]
+name = \"port\"
+type = \"int\"
+default = 8080",
+            )
+            .add_file("project/main.rs", "fn main() {}");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "create",
+                &format!("./{folder}/project"),
+                "--description",
+                "Web service",
+                "--tag",
+                "rust",
+                "--tag",
+                "api",
+            ])
+            .assert()
+            .success()
+            .stdout("info_snapshot\n");
+
+        test_folder
+            .setuprs(&["snapshot", "info", "info_snapshot"])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Snapshot: info_snapshot@1\nDescription: Web service\nAuthor: Jane\nTags: rust, api\n",
+            ))
+            .stdout(predicate::str::contains("Files: 2\n"))
+            .stdout(predicate::str::contains(format!(
+                "Setuprs version: {}\n",
+                env!("CARGO_PKG_VERSION")
+            )))
+            .stdout(predicate::str::ends_with(
+                "Variables:\n  port: int [default: 8080]\n",
+            ));
+
+        checkout_snapshot(&test_folder, "info_snapshot");
+        assert!(!test_folder.join("info_snapshot/.manifests").exists());
+    }
+
     #[test]
     fn on_snapshot_show_should_return_snapshots_alphabetcly() {
-        let noisy = &mut Noisy::new()
-            .add_snapshot_folder_config()
+        let test_folder = TestFolder::new()
+            .with_config()
             .add_folder("snapshots")
             .add_folder("snapshots/snap_1")
             .add_folder("snapshots/snap_2");
 
-        let folder = noisy.folder();
-
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-
-        cmd.arg("--config")
-            .arg(format!("./{folder}/file.toml"))
-            .arg("snapshot")
-            .arg("show")
+        test_folder
+            .setuprs(&["snapshot", "show"])
             .assert()
             .success()
-            .stdout(predicates::str::contains("snap_1\nsnap_2"));
+            .stdout(
+                predicate::str::is_match("^ID +VERSION +DESCRIPTION .*\nsnap_1 .*\nsnap_2 .*\n$")
+                    .unwrap(),
+            );
     }
 
     #[test]
     fn on_snapshot_show_should_return_empty_when_snapshots_folder_does_not_exist() {
-        let noisy = &mut Noisy::new().add_snapshot_folder_config();
-
-        let folder = noisy.folder();
+        let test_folder = TestFolder::new().with_config();
 
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-
-        cmd.arg("--config")
-            .arg(format!("./{folder}/file.toml"))
-            .arg("snapshot")
-            .arg("show")
+        test_folder
+            .setuprs(&["snapshot", "show"])
             .assert()
             .success()
             .stdout(predicate::str::contains("No snapshots on"));
     }
 
     #[test]
-    fn on_snapshot_clone_should_return_err_when_snapshot_dont_exist() {
-        let noisy = &mut Noisy::new().add_snapshot_folder_config();
-        let folder = noisy.folder();
+    fn on_snapshot_show_should_filter_sort_and_format_snapshots() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("project")
+            .add_file("project/setuprs.toml", "[[variables]]\nname = 'port'")
+            .add_file("project/main.rs", "fn main() {}");
+
+        let folder = test_folder.path().display().to_string();
+
+        let project = format!("{folder}/project");
+        test_folder
+            .setuprs(&[
+                "snapshot", "create", &project, "-n", "web_api", "--tag", "web",
+            ])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&[
+                "snapshot", "create", &project, "-n", "cli_tool", "--tag", "cli",
+            ])
+            .assert()
+            .success();
+        fs::write(format!("{folder}/snapshots/notes.txt"), "").unwrap();
 
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
+        test_folder
+            .setuprs(&["snapshot", "show", "--format", "plain"])
+            .assert()
+            .success()
+            .stdout("cli_tool\nweb_api\n");
+        test_folder
+            .setuprs(&["snapshot", "show", "--format", "plain", "--reverse"])
+            .assert()
+            .success()
+            .stdout("web_api\ncli_tool\n");
+        test_folder
+            .setuprs(&["snapshot", "show", "--format", "plain", "--tag", "web"])
+            .assert()
+            .success()
+            .stdout("web_api\n");
+        test_folder
+            .setuprs(&["snapshot", "show", "--format", "plain", "--filter", "cli*"])
+            .assert()
+            .success()
+            .stdout("cli_tool\n");
+        test_folder
+            .setuprs(&["snapshot", "show", "--filter", "[cli"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid filter [cli"));
+        test_folder
+            .setuprs(&["snapshot", "show", "--tag", "web"])
+            .assert()
+            .success()
+            .stdout(predicate::str::is_match("\nweb_api +1 +- +web +1 +").unwrap());
 
-        cmd.arg("--config")
-            .arg(format!("./{folder}/file.toml"))
-            .arg("snapshot")
-            .arg("clone")
-            .arg("snap_inexistent")
-            .arg("-d")
-            .arg(format!("{}/clone_snap_1", &folder))
+        let output = test_folder
+            .setuprs(&["snapshot", "show", "--format", "json"])
+            .assert()
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let summaries: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(summaries[0]["id"], "cli_tool");
+        assert_eq!(summaries[0]["version"], 1);
+        assert_eq!(summaries[0]["tags"], serde_json::json!(["cli"]));
+        assert_eq!(summaries[0]["variables"], 1);
+        assert_eq!(summaries.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_return_err_when_snapshot_dont_exist() {
+        let test_folder = TestFolder::new().with_config();
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_inexistent",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
             .assert()
             .failure()
             .stderr(predicate::str::contains("Provided snapshot don\'t exist"));
@@ -294,26 +600,22 @@ mod tests {
 
     #[test]
     fn on_snapshot_clone_should_copy_from_snapshot_id() {
-        let noisy = &mut Noisy::new()
-            .add_snapshot_folder_config()
+        let test_folder = TestFolder::new()
+            .with_config()
             .add_folder("snapshots")
             .add_folder("snapshots/snap_1")
-            .add_file(NoisyFile {
-                name: "snapshots/snap_1/mockfile",
-                content: "",
-            });
-
-        let folder = noisy.folder();
-
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-
-        cmd.arg("--config")
-            .arg(format!("./{folder}/file.toml"))
-            .arg("snapshot")
-            .arg("clone")
-            .arg("snap_1")
-            .arg("-d")
-            .arg(format!("{}/clone_snap_1", &folder))
+            .add_file("snapshots/snap_1/mockfile", "");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
             .assert()
             .success()
             .stdout(predicate::str::contains("Snapshot created in:"));
@@ -323,39 +625,39 @@ mod tests {
 
     #[test]
     fn on_snapshot_clone_snapshots_should_create_and_replace_variables() {
-        let noisy = &mut Noisy::new()
-            .add_snapshot_folder_config()
+        let test_folder = TestFolder::new()
+            .with_config()
             .add_folder("snapshots")
             .add_folder("snapshots/snap_1")
-            .add_file(NoisyFile {
-                name: "snapshots/snap_1/setuprs.toml",
-                content: "[project]
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[project]
 name = 'snap_1'
 [[variables]]
 name = 'var0'
 [[variables]]
 name = 'var1'
 default = 'value1'",
-            })
-            .add_file(NoisyFile {
-                name: "snapshots/snap_1/replaced_file_0.txt",
-                content: "this value should be replaced -> var0 = {{var0}}",
-            })
-            .add_file(NoisyFile {
-                name: "snapshots/snap_1/replaced_file_1.txt",
-                content: "this value should be replaced -> var1 = {{var1}}",
-            });
-
-        let folder = noisy.folder.clone();
-
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-        cmd.arg("--config")
-            .arg(format!("./{folder}/file.toml"))
-            .arg("snapshot")
-            .arg("clone")
-            .arg("snap_1")
-            .arg("-d")
-            .arg(format!("{}/clone_snap_1", &folder))
+            )
+            .add_file(
+                "snapshots/snap_1/replaced_file_0.txt",
+                "this value should be replaced -> var0 = {{var0}}",
+            )
+            .add_file(
+                "snapshots/snap_1/replaced_file_1.txt",
+                "this value should be replaced -> var1 = {{var1}}",
+            );
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
             .write_stdin("value0")
             .assert()
             .success()
@@ -379,17 +681,1129 @@ default = 'value1'",
         );
     }
 
+    #[test]
+    fn on_snapshot_clone_should_render_conditionals_and_loops() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[project]
+name = 'snap_1'
+[[variables]]
+name = 'use_docker'
+default = 'yes'
+[[variables]]
+name = 'services'
+default = 'api,db'",
+            )
+            .add_file(
+                "snapshots/snap_1/compose.yml",
+                "{% if use_docker == 'yes' %}
+services:
+{% for service in services | split(',') %}
+  {{service}}: {}
+{% endfor %}
+{% endif %}
+",
+            );
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
+            .write_stdin("\n\n")
+            .assert()
+            .success();
+
+        let rendered = fs::read_to_string(format!("{folder}/clone_snap_1/compose.yml")).unwrap();
+
+        assert_eq!("services:\n  api: {}\n  db: {}\n", rendered);
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_render_file_names() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[project]
+name = 'snap_1'
+[[variables]]
+name = 'project_name'
+[[variables]]
+name = 'use_docker'",
+            )
+            .add_file("snapshots/snap_1/{{project_name}}.service", "")
+            .add_file(
+                "snapshots/snap_1/{% if use_docker == 'yes' %}Dockerfile{% endif %}",
+                "",
+            );
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
+            .write_stdin("my_service\nno\n")
+            .assert()
+            .success();
+
+        assert!(Path::new(&format!("{folder}/clone_snap_1/my_service.service")).exists());
+        assert!(!Path::new(&format!("{folder}/clone_snap_1/Dockerfile")).exists());
+    }
+
+    #[test]
+    fn on_snapshot_clone_dry_run_should_preview_without_writing() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_folder("clone_snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[[variables]]\nname = 'name'",
+            )
+            .add_file("snapshots/snap_1/{{name}}.md", "# {{name}}\n")
+            .add_file("snapshots/snap_1/.setuprsignore", "notes.txt")
+            .add_file("snapshots/snap_1/notes.txt", "")
+            .add_file("clone_snap_1/app.md", "# old\n")
+            .add_file("clone_snap_1/setuprs.toml", "[[variables]]\nname = 'name'");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "--dry-run",
+                "--diff",
+                "-d",
+                &format!("{folder}/clone_snap_1"),
+                "--set",
+                "name=app",
+            ])
+            .assert()
+            .success()
+            .stdout(
+                "Create:\n  .setuprs-answers.toml\n  .setuprsignore\n\
+                 Overwrite:\n  app.md\n\
+                 Unchanged:\n  setuprs.toml\n\
+                 2 to create, 1 to overwrite, 1 unchanged\n\
+                 --- a/app.md\n+++ b/app.md\n@@ -1 +1 @@\n-# old\n+# app\n",
+            );
+
+        test_folder
+            .setuprs(&["snapshot", "clone", "snap_1", "--diff"])
+            .assert()
+            .failure();
+
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/clone_snap_1/app.md")).unwrap(),
+            "# old\n"
+        );
+        assert!(!Path::new(&format!("{folder}/clone_snap_1/.setuprs-answers.toml")).exists());
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_refuse_to_overwrite_modified_files_by_default() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_folder("clone_snap_1")
+            .add_file("snapshots/snap_1/setuprs.toml", "")
+            .add_file("snapshots/snap_1/README.md", "snapshot")
+            .add_file("snapshots/snap_1/main.rs", "fn main() {}")
+            .add_file("clone_snap_1/README.md", "mine");
+
+        let folder = test_folder.path().display().to_string();
+        let clone = |on_conflict: &str, stdin: &str| {
+            test_folder
+                .setuprs(&[
+                    "snapshot",
+                    "clone",
+                    "snap_1",
+                    "--on-conflict",
+                    on_conflict,
+                    "-d",
+                    &format!("{folder}/clone_snap_1"),
+                ])
+                .write_stdin(stdin)
+                .assert()
+        };
+        let read =
+            |file: &str| fs::read_to_string(format!("{folder}/clone_snap_1/{file}")).unwrap();
+
+        clone("fail", "").failure().stderr(predicate::str::contains(
+            "Clone would overwrite modified files: README.md",
+        ));
+        assert!(!Path::new(&format!("{folder}/clone_snap_1/main.rs")).exists());
+
+        clone("prompt", "n\n")
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Overwrite README.md? [y/N] Skipped, modified locally:\n  README.md\n",
+            ));
+        assert_eq!(read("README.md"), "mine");
+        assert_eq!(read("main.rs"), "fn main() {}");
+
+        clone("backup", "")
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Replaced, backed up:\n  README.md (original in README.md.bak)\n",
+            ));
+        assert_eq!(read("README.md"), "snapshot");
+        assert_eq!(read("README.md.bak"), "mine");
+
+        // Nothing left to conflict once the clone matches the snapshot
+        clone("fail", "").success();
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_use_typed_variables() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[project]
+name = 'snap_1'
+[[variables]]
+name = 'use_docker'
+type = 'bool'
+[[variables]]
+name = 'port'
+type = 'int'
+max = 65535
+[[variables]]
+name = 'services'
+type = 'list'",
+            )
+            .add_file(
+                "snapshots/snap_1/config.txt",
+                "{% if use_docker %}docker{% endif %}:{{port + 1}}:{{services | join('|')}}",
+            );
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
+            .write_stdin("yse\nyes\n80800\n8080\napi, db\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "Invalid value for use_docker: expected yes or no",
+            ))
+            .stdout(predicate::str::contains(
+                "Invalid value for port: 80800 is out of range",
+            ));
+
+        let rendered = fs::read_to_string(format!("{folder}/clone_snap_1/config.txt")).unwrap();
+
+        assert_eq!("docker:8081:api|db", rendered);
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_fail_when_input_ends_without_valid_answer() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[[variables]]
+name = 'use_docker'
+type = 'bool'",
+            );
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
+            .write_stdin("maybe\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid value for use_docker"));
+
+        assert!(!Path::new(&format!("{folder}/clone_snap_1")).exists());
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_not_prompt_with_set_answers_and_defaults() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[[variables]]
+name = 'name'
+[[variables]]
+name = 'port'
+type = 'int'
+[[variables]]
+name = 'author'
+default = 'me'",
+            )
+            .add_file("answers.json", "{\"port\": 8080}")
+            .add_file("snapshots/snap_1/file.txt", "{{name}}:{{port}}:{{author}}");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+                "--set",
+                "name=app=1",
+                "--answers",
+                &format!("{folder}/answers.json"),
+                "--defaults",
+            ])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Enter value").not());
+
+        let rendered = fs::read_to_string(format!("{folder}/clone_snap_1/file.txt")).unwrap();
+
+        assert_eq!("app=1:8080:me", rendered);
+    }
+
+    #[test]
+    fn on_snapshot_clone_with_defaults_should_list_variables_without_value() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[[variables]]
+name = 'name'
+[[variables]]
+name = 'port'
+type = 'int'
+[[variables]]
+name = 'author'
+default = 'me'",
+            );
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+                "--defaults",
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "Missing values for variables without default: name, port",
+            ));
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_record_answers_and_reuse_them() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file(
+                "snapshots/snap_1/setuprs.toml",
+                "[[variables]]
+name = 'name'
+[[variables]]
+name = 'port'
+type = 'int'",
+            )
+            .add_file("snapshots/snap_1/file.txt", "{{name}}:{{port}}");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
+            .write_stdin("app\n8080\n")
+            .assert()
+            .success();
+
+        let recorded =
+            fs::read_to_string(format!("{folder}/clone_snap_1/.setuprs-answers.toml")).unwrap();
+
+        assert!(recorded.contains("snapshot_id = \"snap_1\""));
+        assert!(recorded.contains("snapshot_hash = "));
+        assert!(recorded.contains("[answers]\nname = \"app\"\nport = 8080"));
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_2", &folder),
+                "--answers",
+                &format!("{folder}/clone_snap_1/.setuprs-answers.toml"),
+                "--defaults",
+            ])
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/clone_snap_1/file.txt")).unwrap(),
+            fs::read_to_string(format!("{folder}/clone_snap_2/file.txt")).unwrap()
+        );
+        assert_eq!(
+            recorded,
+            fs::read_to_string(format!("{folder}/clone_snap_2/.setuprs-answers.toml")).unwrap()
+        );
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_report_file_and_line_when_template_fails() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("snapshots")
+            .add_folder("snapshots/snap_1")
+            .add_file("snapshots/snap_1/broken.txt", "first line\n{% if %}\n");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "snap_1",
+                "-d",
+                &format!("{}/clone_snap_1", &folder),
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("template broken.txt:2"));
+    }
+
+    #[test]
+    fn on_update_should_merge_new_snapshot_version_into_project() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("project")
+            .add_file(
+                "project/setuprs.toml",
+                "[project]
+name = 'tpl'
+[[variables]]
+name = 'name'",
+            )
+            .add_file("project/ci.yml", "a\n{{name}}\n");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "tpl",
+                "-d",
+                &format!("{folder}/app"),
+                "--set",
+                "name=x",
+            ])
+            .assert()
+            .success();
+
+        test_folder
+            .setuprs(&["update", "-d", &format!("{folder}/app")])
+            .assert()
+            .success()
+            .stdout("Already up to date\n");
+
+        fs::write(format!("{folder}/app/ci.yml"), "local\na\nx\n").unwrap();
+        fs::write(format!("{folder}/project/ci.yml"), "a\n{{name}}\nd\n").unwrap();
+        fs::write(format!("{folder}/project/lint.toml"), "{{name}}").unwrap();
+
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&["update", "-d", &format!("{folder}/app")])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "Added:\n  lint.toml\nUpdated:\n  ci.yml",
+            ));
+
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/app/ci.yml")).unwrap(),
+            "local\na\nx\nd\n"
+        );
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/app/lint.toml")).unwrap(),
+            "x"
+        );
+
+        test_folder
+            .setuprs(&["update", "-d", &format!("{folder}/app")])
+            .assert()
+            .success()
+            .stdout("Already up to date\n");
+    }
+
+    #[test]
+    fn on_snapshot_create_should_keep_immutable_versions_to_clone_from() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("project")
+            .add_file("project/setuprs.toml", "[project]\nname = 'tpl'")
+            .add_file("project/main.rs", "v1")
+            .add_file("project/old.rs", "old");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        for name in ["tpl@2", "../tpl", "team/tpl"] {
+            test_folder
+                .setuprs(&[
+                    "snapshot",
+                    "create",
+                    &format!("{folder}/project"),
+                    "-n",
+                    name,
+                ])
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains(format!(
+                    "Invalid snapshot id {name}"
+                )));
+        }
+
+        fs::write(format!("{folder}/project/main.rs"), "v2").unwrap();
+        fs::remove_file(format!("{folder}/project/old.rs")).unwrap();
+
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        // Nothing changed, no new version
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+
+        test_folder
+            .setuprs(&["snapshot", "versions", "tpl"])
+            .assert()
+            .success()
+            .stdout(predicate::str::is_match("^tpl@1 .*\ntpl@2 .*  \\(latest\\)\n$").unwrap());
+
+        // Only the mode changed, a new version all the same
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let main = format!("{folder}/project/main.rs");
+            fs::set_permissions(&main, fs::Permissions::from_mode(0o755)).unwrap();
+            test_folder
+                .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+                .assert()
+                .success();
+            test_folder
+                .setuprs(&["snapshot", "versions", "tpl"])
+                .assert()
+                .success()
+                .stdout(predicate::str::contains("tpl@3 "));
+        }
+
+        let v2 = Tree::load(&test_config(&folder), "tpl", 2).unwrap();
+        assert!(!v2.entries.iter().any(|entry| entry.path == "old.rs"));
+
+        test_folder
+            .setuprs(&["snapshot", "clone", "tpl@1", "-d", &format!("{folder}/v1")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&["snapshot", "clone", "tpl", "-d", &format!("{folder}/v2")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&["snapshot", "clone", "tpl@9", "-d", &format!("{folder}/v9")])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("tpl@9"));
+
+        let read = |file: &str| fs::read_to_string(format!("{folder}/{file}")).ok();
+
+        assert_eq!(read("v1/main.rs").unwrap(), "v1");
+        assert_eq!(read("v1/old.rs").unwrap(), "old");
+        assert_eq!(read("v2/main.rs").unwrap(), "v2");
+        assert_eq!(read("v2/old.rs"), None);
+        assert!(read("v1/.setuprs-answers.toml")
+            .unwrap()
+            .contains("snapshot_version = 1"));
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_report_template_errors_relative_to_the_snapshot() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("project")
+            .add_folder("project/src")
+            .add_file(
+                "project/setuprs.toml",
+                "[project]\nname = 'tpl'\n[[computed]]\nname = 'slug'\nvalue = '{{ name'",
+            )
+            .add_file("project/src/main.rs", "fn main() { {{ broken }");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&["snapshot", "clone", "tpl@1", "-d", &format!("{folder}/app")])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "Failed to render template setuprs.toml (computed slug):1",
+            ));
+
+        fs::write(
+            format!("{folder}/project/setuprs.toml"),
+            "[project]\nname = 'tpl'",
+        )
+        .unwrap();
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&["snapshot", "clone", "tpl@2", "-d", &format!("{folder}/app")])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(format!(
+                "Failed to render template src{}main.rs:1",
+                std::path::MAIN_SEPARATOR
+            )))
+            .stderr(predicate::str::contains("setuprs-").not());
+    }
+
+    #[test]
+    fn on_gc_should_keep_shared_files_once_and_prune_unreferenced_ones() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("project")
+            .add_file("project/setuprs.toml", "")
+            .add_file("project/main.rs", "fn main() {}");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "create",
+                &format!("{folder}/project"),
+                "-n",
+                "a",
+            ])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "create",
+                &format!("{folder}/project"),
+                "-n",
+                "b",
+            ])
+            .assert()
+            .success();
+
+        let count_blobs = || {
+            fs::read_dir(format!("{folder}/snapshots/.objects"))
+                .unwrap()
+                .map(|prefix| fs::read_dir(prefix.unwrap().path()).unwrap().count())
+                .sum::<usize>()
+        };
+        assert_eq!(count_blobs(), 2);
+
+        fs::create_dir_all(format!("{folder}/snapshots/.objects/ff")).unwrap();
+        fs::write(format!("{folder}/snapshots/.objects/ff/unused"), "unused").unwrap();
+
+        test_folder
+            .setuprs(&["gc"])
+            .assert()
+            .success()
+            .stdout("Removed 1 unreferenced objects, 6 bytes freed\n");
+        test_folder
+            .setuprs(&["gc"])
+            .assert()
+            .success()
+            .stdout("Removed 0 unreferenced objects, 0 bytes freed\n");
+
+        test_folder
+            .setuprs(&["snapshot", "clone", "a", "-d", &format!("{folder}/clone")])
+            .assert()
+            .success();
+
+        assert_eq!(count_blobs(), 2);
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/clone/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+    }
+
+    #[test]
+    fn on_snapshot_import_should_restore_exported_snapshot() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("project")
+            .add_file("project/setuprs.toml", "[project]\nname = 'shared'")
+            .add_file("project/main.rs", "fn main() {}");
+
+        let folder = test_folder.path().display().to_string();
+        let archive = format!("{folder}/shared.tar.gz");
+
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&["snapshot", "export", "shared", "-o", &archive])
+            .assert()
+            .success()
+            .stdout(format!("Snapshot shared exported to {archive}\n"));
+
+        test_folder
+            .setuprs(&["snapshot", "import", &archive])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Snapshot shared already exists"));
+        test_folder
+            .setuprs(&["snapshot", "import", &archive, "--force"])
+            .assert()
+            .success()
+            .stdout("shared\n");
+        test_folder
+            .setuprs(&["snapshot", "import", &archive, "--as", "copy"])
+            .assert()
+            .success()
+            .stdout("copy\n");
+
+        test_folder
+            .setuprs(&["snapshot", "info", "copy"])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("Snapshot: copy@1\n"));
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "copy",
+                "-d",
+                &format!("{folder}/clone"),
+            ])
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/clone/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+    }
+
+    #[test]
+    fn on_snapshot_clone_should_copy_from_folder_or_git_repository() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("repo")
+            .add_folder("repo/template")
+            .add_file("repo/template/setuprs.toml", "[[variables]]\nname = 'name'")
+            .add_file("repo/template/README.md", "v1 {{name}}");
+
+        let folder = test_folder.path().display().to_string();
+
+        let git = |dir: &str, args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=setuprs",
+                    "-c",
+                    "user.email=setuprs@example.com",
+                ])
+                .args(args)
+                .current_dir(format!("{folder}/{dir}"))
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success());
+        };
+
+        git("repo", &["init", "-q"]);
+        git("repo", &["add", "."]);
+        git("repo", &["commit", "-q", "-m", "v1"]);
+        git("repo", &["tag", "v1"]);
+        fs::write(format!("{folder}/repo/template/README.md"), "v2 {{name}}").unwrap();
+        git("repo", &["commit", "-q", "-am", "v2"]);
+        git(".", &["clone", "-q", "--bare", "repo", "bare.git"]);
+
+        let bare = fs::canonicalize(format!("{folder}/bare.git")).unwrap();
+        let url = format!("file://{}", bare.display());
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                &format!("./{folder}/repo/template"),
+                "-d",
+                &format!("{folder}/from_folder"),
+                "--set",
+                "name=folder",
+            ])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                &url,
+                "--ref",
+                "v1",
+                "--subdir",
+                "template",
+                "-d",
+                &format!("{folder}/from_tag"),
+                "--set",
+                "name=tag",
+            ])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                &format!("{folder}/bare.git"),
+                "--subdir",
+                "template",
+                "-d",
+                &format!("{folder}/from_head"),
+                "--set",
+                "name=head",
+            ])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                &url,
+                "--ref",
+                "v9",
+                "-d",
+                &format!("{folder}/v9"),
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(format!("Can't clone from {url}")));
+
+        let read = |file: &str| fs::read_to_string(format!("{folder}/{file}")).unwrap();
+
+        assert_eq!(read("from_folder/README.md"), "v2 folder");
+        assert_eq!(read("from_tag/README.md"), "v1 tag");
+        assert_eq!(read("from_head/README.md"), "v2 head");
+        assert!(!Path::new(&format!("{folder}/from_tag/.git")).exists());
+        assert!(read("from_tag/.setuprs-answers.toml").contains(&url));
+
+        fs::write(format!("{folder}/repo/template/README.md"), "v3 {{name}}").unwrap();
+        git("repo", &["commit", "-q", "-am", "v3"]);
+        git("repo", &["push", "-q", "../bare.git", "HEAD"]);
+
+        // Updated from the same repository, ref and folder they were cloned from
+        test_folder
+            .setuprs(&["update", "-d", &format!("{folder}/from_head")])
+            .assert()
+            .success();
+        test_folder
+            .setuprs(&["update", "-d", &format!("{folder}/from_tag")])
+            .assert()
+            .success()
+            .stdout("Already up to date\n");
+        test_folder
+            .setuprs(&["update", "-d", &format!("{folder}/from_folder")])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cloned from a project folder"));
+
+        assert_eq!(read("from_head/README.md"), "v3 head");
+        assert_eq!(read("from_tag/README.md"), "v1 tag");
+        assert!(read("from_tag/.setuprs-answers.toml").contains("git_ref = \"v1\""));
+    }
+
+    #[test]
+    fn on_snapshot_delete_rename_and_duplicate_should_keep_versions_and_metadata() {
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_folder("project")
+            .add_file(
+                "project/setuprs.toml",
+                "[project]\nname = 'tpl'\ndescription = 'Template'",
+            )
+            .add_file("project/main.rs", "v1");
+
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+        fs::write(format!("{folder}/project/main.rs"), "v2").unwrap();
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("{folder}/project")])
+            .assert()
+            .success();
+
+        test_folder
+            .setuprs(&["snapshot", "rename", "tpl", "renamed"])
+            .assert()
+            .success()
+            .stdout("renamed\n");
+        test_folder
+            .setuprs(&["snapshot", "duplicate", "renamed", "copy"])
+            .assert()
+            .success()
+            .stdout("copy\n");
+        test_folder
+            .setuprs(&["snapshot", "duplicate", "renamed", "copy"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Snapshot copy already exists"));
+        test_folder
+            .setuprs(&["snapshot", "rename", "copy", "bad@id"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid snapshot id bad@id"));
+
+        test_folder
+            .setuprs(&["snapshot", "info", "copy@1"])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with(
+                "Snapshot: copy@1\nDescription: Template\n",
+            ));
+        test_folder
+            .setuprs(&["snapshot", "versions", "renamed"])
+            .assert()
+            .success()
+            .stdout(
+                predicate::str::is_match("^renamed@1 .*\nrenamed@2 .*  \\(latest\\)\n$").unwrap(),
+            );
+
+        test_folder
+            .setuprs(&["snapshot", "delete", "renamed"])
+            .write_stdin("n\n")
+            .assert()
+            .success()
+            .stdout("Delete renamed and its 2 version(s)? [y/N] Aborted\n");
+
+        test_folder
+            .setuprs(&["snapshot", "delete", "renamed", "--yes"])
+            .assert()
+            .success()
+            .stdout("Deleted renamed\n");
+        test_folder
+            .setuprs(&["snapshot", "delete", "renamed", "--yes"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Provided snapshot don't exist"));
+
+        test_folder
+            .setuprs(&["snapshot", "show", "--format", "plain"])
+            .assert()
+            .success()
+            .stdout("copy\n");
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "clone",
+                "copy@1",
+                "-d",
+                &format!("{folder}/clone"),
+            ])
+            .assert()
+            .success();
+
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/clone/main.rs")).unwrap(),
+            "v1"
+        );
+    }
+
+    #[test]
+    fn on_publish_should_share_snapshots_to_search_and_pull_from_registries() {
+        let test_folder = TestFolder::new()
+            .add_folder("project")
+            .add_file(
+                "project/setuprs.toml",
+                "[project]
+name = 'web'
+description = 'Axum service'
+tags = ['rust']",
+            )
+            .add_file("project/main.rs", "fn main() {}");
+
+        let folder = test_folder.path().display().to_string();
+        let root = fs::canonicalize(&folder).unwrap();
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(args)
+                .current_dir(&root)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+        };
+        git(&["init", "-q", "--bare", "team.git"]);
+        fs::create_dir(root.join("local")).unwrap();
+        let http = serve(root.join("local"));
+
+        fs::write(
+            test_folder.join("file.toml"),
+            format!(
+                "config_file_path = '.'
+debug_mode = 'error'
+snapshots_path = '{folder}/snapshots'
+
+[[registries]]
+name = 'local'
+url = '{root}/local'
+
+[[registries]]
+name = 'team'
+url = 'file://{root}/team.git'
+
+[[registries]]
+name = 'remote'
+url = '{http}'",
+                root = root.display()
+            ),
+        )
+        .unwrap();
+
+        let setuprs = |args: &[&str]| {
+            test_folder
+                .setuprs(args)
+                .env("GIT_AUTHOR_NAME", "setuprs")
+                .env("GIT_AUTHOR_EMAIL", "setuprs@example.com")
+                .env("GIT_COMMITTER_NAME", "setuprs")
+                .env("GIT_COMMITTER_EMAIL", "setuprs@example.com")
+                .assert()
+        };
+
+        setuprs(&["snapshot", "create", &format!("{folder}/project")]).success();
+        setuprs(&["publish", "web"])
+            .failure()
+            .stderr(predicate::str::contains("3 registries are configured"));
+        setuprs(&["publish", "web", "-r", "local"])
+            .success()
+            .stdout("Published web@1 to local\n");
+        setuprs(&["publish", "web", "-r", "team"])
+            .success()
+            .stdout("Published web@1 to team\n");
+
+        setuprs(&["search", "axum"]).success().stdout(
+            "local/web@1  Axum service  [rust]
+team/web@1  Axum service  [rust]
+remote/web@1  Axum service  [rust]\n",
+        );
+        setuprs(&["search", "python"]).success().stdout("");
+
+        setuprs(&["pull", "local/web"])
+            .failure()
+            .stderr(predicate::str::contains("Snapshot web already exists"));
+        setuprs(&["pull", "local/nope"])
+            .failure()
+            .stderr(predicate::str::contains("Registry local: no snapshot nope"));
+        setuprs(&["pull", "team/web", "--as", "from_git"])
+            .success()
+            .stdout("from_git\n");
+        setuprs(&["pull", "remote/web", "--as", "from_http"])
+            .success()
+            .stdout("from_http\n");
+
+        setuprs(&[
+            "snapshot",
+            "clone",
+            "from_http",
+            "-d",
+            &format!("{folder}/clone"),
+        ])
+        .success();
+        setuprs(&["snapshot", "info", "from_git"])
+            .success()
+            .stdout(predicate::str::contains("Description: Axum service\n"));
+
+        assert_eq!(
+            fs::read_to_string(format!("{folder}/clone/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+    }
+
     #[test]
     fn on_init_set_default_snapshot_config_on_init() {
-        let Noisy { folder, cleanup: _ } = &Noisy::new().add_config();
+        let test_folder = TestFolder::new();
+        let folder = test_folder.path().display().to_string();
 
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
         let path_ignore = format!("./{folder}/.setuprsignore");
         let path_config = format!("./{folder}/setuprs.toml");
 
+        let mut cmd = Command::cargo_bin("setuprs").unwrap();
         cmd.arg("init")
             .arg("-d")
-            .arg(folder)
+            .arg(&folder)
             .write_stdin("project_name")
             .assert()
             .success();
@@ -412,7 +1826,6 @@ name = \"project_name\"\n
     #[test]
     fn on_config_current_config_should_return_correct_default_info() {
         let mut cmd = Command::cargo_bin("setuprs").unwrap();
-
         let value = cmd
             .arg("config")
             .arg("show")
@@ -431,14 +1844,10 @@ name = \"project_name\"\n
 
     #[test]
     fn on_config_current_config_should_return_correct_info_after_define_new_config() {
-        let Noisy { folder, cleanup: _ } = &Noisy::new().add_config();
+        let test_folder = TestFolder::new().with_config();
 
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-        let value = cmd
-            .arg("--config")
-            .arg(format!("./{folder}/file.toml"))
-            .arg("config")
-            .arg("show")
+        let value = test_folder
+            .setuprs(&["config", "show"])
             .assert()
             .success()
             .get_output()
@@ -448,123 +1857,70 @@ name = \"project_name\"\n
 
         assert_eq!(
             Config::from_str(raw_stdout.unwrap().as_ref()).unwrap(),
-            Config {
-                config_file_path: ".".to_string(),
-                debug_mode: "error".to_string(),
-                snapshots_path: ".".to_string()
-            }
+            test_config(test_folder.path())
         )
     }
 
     #[test]
     fn on_snapshot_create_should_create_snapshot_with_setuprs_toml_project_name() {
-        let noisy = &mut Noisy::new().add_config().add_file(NoisyFile {
-            name: "setuprs.toml",
-            content: "[project]\nname=\"project\"",
-        });
-        let folder = &noisy.folder;
-        let file = "file.toml";
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_file("project/setuprs.toml", "[project]\nname=\"project\"");
+        let folder = test_folder.path().display().to_string();
 
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-        let value = cmd
-            .arg("--config")
-            .arg(format!("./{folder}/{file}"))
-            .arg("snapshot")
-            .arg("create")
-            .arg(format!("./{folder}"))
+        let value = test_folder
+            .setuprs(&["snapshot", "create", &format!("./{folder}/project")])
             .assert()
             .get_output()
             .clone();
 
         let binding = String::from_utf8(value.stdout).unwrap();
-        let snapshot_file = binding
-            .lines()
-            .next()
-            .expect("No second line found")
-            .to_string();
+        let snapshot_id = binding.lines().next().expect("No snapshot id printed");
 
-        let snapshot_file_clone = snapshot_file.clone();
+        assert_eq!(snapshot_id, "project");
 
-        noisy.overwrite_cleanup(Box::new(move || {
-            fs::remove_dir_all(&snapshot_file_clone).unwrap();
-        }));
-
-        let read_copied_file: String =
-            fs::read_to_string(format!("./{snapshot_file}/{file}")).unwrap();
-
-        let config: Config = toml::from_str(&read_copied_file).unwrap();
+        checkout_snapshot(&test_folder, snapshot_id);
 
         assert_eq!(
-            config,
-            Config {
-                config_file_path: ".".to_string(),
-                debug_mode: "error".to_string(),
-                snapshots_path: ".".to_string()
-            }
+            fs::read_to_string(test_folder.join("project/setuprs.toml")).unwrap(),
+            "[project]\nname=\"project\""
         );
     }
 
     #[test]
     fn on_snapshot_create_should_create_snapshot_with_uuid() {
-        let noisy = &mut Noisy::new().add_config().add_file(NoisyFile {
-            name: "setuprs.toml",
-            content: "",
-        });
-        let folder = &noisy.folder;
-        let file = "file.toml".to_string();
-
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-        let value = cmd
-            .arg("--config")
-            .arg(format!("./{folder}/{file}"))
-            .arg("snapshot")
-            .arg("create")
-            .arg(format!("./{folder}"))
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_file("project/setuprs.toml", "")
+            .add_file("project/main.rs", "fn main() {}");
+        let folder = test_folder.path().display().to_string();
+
+        let value = test_folder
+            .setuprs(&["snapshot", "create", &format!("./{folder}/project")])
             .assert()
             .get_output()
             .clone();
 
         let binding = String::from_utf8(value.stdout).unwrap();
-        let snapshot_file = binding
-            .lines()
-            .next()
-            .expect("No second line found")
-            .to_string();
-
-        let snapshot_file_clone = snapshot_file.clone();
-
-        noisy.overwrite_cleanup(Box::new(move || {
-            fs::remove_dir_all(&snapshot_file_clone).unwrap();
-        }));
+        let snapshot_id = binding.lines().next().expect("No snapshot id printed");
 
-        let read_copied_file: String =
-            fs::read_to_string(format!("./{snapshot_file}/{file}")).unwrap();
+        assert!(uuid::Uuid::parse_str(snapshot_id).is_ok());
 
-        let config: Config = toml::from_str(&read_copied_file).unwrap();
+        checkout_snapshot(&test_folder, snapshot_id);
 
         assert_eq!(
-            config,
-            Config {
-                config_file_path: ".".to_string(),
-                debug_mode: "error".to_string(),
-                snapshots_path: ".".to_string()
-            }
+            fs::read_to_string(test_folder.join(snapshot_id).join("main.rs")).unwrap(),
+            "fn main() {}"
         );
     }
 
     #[test]
     fn on_snapshot_create_snapshots_should_fail_when_no_setuprsignore() {
-        let noisy = &mut Noisy::new().add_config();
-        let folder = noisy.folder.clone();
+        let test_folder = TestFolder::new().with_config();
+        let folder = test_folder.path().display().to_string();
 
-        let file = "file.toml".to_string();
-
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-        cmd.arg("--config")
-            .arg(format!("./{folder}/{file}"))
-            .arg("snapshot")
-            .arg("create")
-            .arg(format!("./{folder}"))
+        test_folder
+            .setuprs(&["snapshot", "create", &format!("./{folder}")])
             .assert()
             .failure()
             .stderr(predicates::str::contains(
@@ -573,49 +1929,36 @@ name = \"project_name\"\n
     }
 
     #[test]
-    #[serial]
     fn on_snapshot_create_snapshots_created_with_tag_success() {
-        let noisy = &mut Noisy::new().add_config().add_file(NoisyFile {
-            name: "setuprs.toml",
-            content: "",
-        });
-        let folder = noisy.folder.clone();
-
-        let file = "file.toml".to_string();
-
-        noisy.overwrite_cleanup(Box::new(move || {
-            fs::remove_dir_all("tag_name").unwrap();
-        }));
-
-        let mut cmd = Command::cargo_bin("setuprs").unwrap();
-        cmd.arg("--config")
-            .arg(format!("./{folder}/{file}"))
-            .arg("snapshot")
-            .arg("create")
-            .arg(format!("./{folder}"))
-            .arg("-n")
-            .arg("tag_name")
+        let test_folder = TestFolder::new()
+            .with_config()
+            .add_file("project/setuprs.toml", "")
+            .add_file("project/main.rs", "fn main() {}");
+        let folder = test_folder.path().display().to_string();
+
+        test_folder
+            .setuprs(&[
+                "snapshot",
+                "create",
+                &format!("./{folder}/project"),
+                "-n",
+                "tag_name",
+            ])
             .assert()
             .success()
             .stdout("tag_name\n");
 
-        let read_copied_file: String = fs::read_to_string(format!("./tag_name/{file}")).unwrap();
-        let config: Config = toml::from_str(&read_copied_file).unwrap();
+        checkout_snapshot(&test_folder, "tag_name");
 
         assert_eq!(
-            config,
-            Config {
-                config_file_path: ".".to_string(),
-                debug_mode: "error".to_string(),
-                snapshots_path: ".".to_string()
-            }
+            fs::read_to_string(test_folder.join("tag_name/main.rs")).unwrap(),
+            "fn main() {}"
         );
     }
 
     #[test]
     fn should_return_helper_message() {
         let mut cmd = Command::cargo_bin("setuprs").unwrap();
-
         cmd.assert().failure().stderr(predicates::str::contains(
             "Usage: setuprs [OPTIONS] [COMMAND]
 
@@ -623,6 +1966,11 @@ Commands:
   snapshot  Snapshot commands
   config    Configuration options
   init      Prepare folder to create a snapshot
+  update    Re-apply the latest version of a snapshot or git repository to a project cloned from it
+  gc        Remove stored files no snapshot refers to anymore
+  search    Search the snapshots of every registry
+  pull      Add a snapshot of a registry to the local snapshots
+  publish   Share the latest version of a snapshot in a registry
   help      Print this message or the help of the given subcommand(s)
 
 Options:
@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde_derive::{Deserialize, Serialize};
+use toml::Value;
+
+use crate::error::{Error, Result};
+
+use super::{source::SourceKind, template::Renderer, Computed, Variables};
+
+pub type Answers = BTreeMap<String, Value>;
+
+/// Written into every cloned project so the same scaffold can be generated again
+pub const ANSWERS_FILE: &str = ".setuprs-answers.toml";
+
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+pub struct AnswersRecord {
+    /// Id of the snapshot, or reference of the folder or git repository cloned
+    pub snapshot_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_version: Option<u32>,
+    pub snapshot_hash: String,
+    /// Missing in records written before other sources could be cloned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceKind>,
+    /// Branch, tag or commit given to clone a git repository, its default branch if none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Commit the files of a git repository came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_commit: Option<String>,
+    /// Folder of the source holding the snapshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
+    pub answers: Answers,
+}
+
+impl AnswersRecord {
+    pub fn write(&self, destination: impl AsRef<Path>) -> Result<()> {
+        let content = toml::to_string(self)?;
+        fs::write(destination.as_ref().join(ANSWERS_FILE), content)?;
+
+        Ok(())
+    }
+}
+
+/// Reads pre-filled answers from a TOML file, or JSON when the extension is `.json`.
+/// A recorded `.setuprs-answers.toml` is accepted as well, using its `answers` table.
+pub fn load_answers_file(path: &Path) -> Result<Answers> {
+    let content = fs::read_to_string(path)?;
+
+    let answers = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str::<Answers>(&content)?,
+        _ => toml::from_str::<Answers>(&content)?,
+    };
+
+    match (answers.get("answers"), answers.contains_key("snapshot_id")) {
+        (Some(Value::Table(recorded)), true) => Ok(recorded.clone().into_iter().collect()),
+        _ => Ok(answers),
+    }
+}
+
+/// Resolves every variable from the provided answers, its default or the prompt, in that order.
+/// With `use_defaults` nothing is prompted and every unanswered variable without default is
+/// reported at once.
+pub fn resolve_answers<F>(
+    variables: &[Variables],
+    provided: &Answers,
+    use_defaults: bool,
+    mut prompt: F,
+) -> Result<Answers>
+where
+    F: FnMut(&Variables) -> Result<Value>,
+{
+    let mut answers = Answers::new();
+    let mut missing = vec![];
+
+    for var in variables {
+        let invalid = |reason: String| Error::InvalidAnswer {
+            name: var.name.clone(),
+            reason,
+        };
+
+        let value = match provided.get(&var.name) {
+            Some(value) => var.coerce(value).map_err(invalid)?,
+            None if use_defaults => match var.default_value().map_err(invalid)? {
+                Some(default) => default,
+                None => {
+                    missing.push(var.name.clone());
+                    continue;
+                }
+            },
+            None => prompt(var)?,
+        };
+
+        answers.insert(var.name.clone(), value);
+    }
+
+    if !missing.is_empty() {
+        return Err(Error::MissingAnswers(missing.join(", ")));
+    }
+
+    Ok(answers)
+}
+
+/// Adds the computed variables to the answers, each one can use the ones declared before it
+pub fn apply_computed(answers: &Answers, computed: &[Computed], source: &Path) -> Result<Answers> {
+    let mut answers = answers.clone();
+
+    for var in computed {
+        let name = format!("{} (computed {})", source.display(), var.name);
+        let value = Renderer::new(&answers).render(Path::new(&name), &var.value)?;
+
+        answers.insert(var.name.clone(), Value::String(value));
+    }
+
+    Ok(answers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use toml::Value;
+
+    use crate::{
+        core::{testing::TestFolder, Variables},
+        error::Error,
+    };
+
+    use super::{
+        apply_computed, load_answers_file, resolve_answers, Answers, AnswersRecord, ANSWERS_FILE,
+    };
+
+    fn variables() -> Vec<Variables> {
+        toml::from_str::<crate::core::SetuprsConfig>(
+            "[[variables]]
+name = 'name'
+[[variables]]
+name = 'port'
+type = 'int'
+default = 8080
+[[variables]]
+name = 'author'",
+        )
+        .unwrap()
+        .variables
+        .unwrap()
+    }
+
+    #[test]
+    fn should_prefer_provided_answers_over_prompt() {
+        let provided = Answers::from([
+            ("name".to_string(), Value::String("app".into())),
+            ("port".to_string(), Value::String("3000".into())),
+        ]);
+
+        let answers = resolve_answers(&variables(), &provided, false, |var| {
+            Ok(Value::String(format!("prompted {}", var.name)))
+        })
+        .unwrap();
+
+        assert_eq!(answers["name"], Value::String("app".into()));
+        assert_eq!(answers["port"], Value::Integer(3000));
+        assert_eq!(answers["author"], Value::String("prompted author".into()));
+    }
+
+    #[test]
+    fn should_list_every_missing_variable_when_using_defaults() {
+        let err = resolve_answers(&variables(), &Answers::new(), true, |_| {
+            panic!("should not prompt")
+        })
+        .unwrap_err();
+
+        match err {
+            Error::MissingAnswers(names) => assert_eq!(names, "name, author"),
+            _ => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn should_compute_variables_from_answers_and_previous_computed() {
+        let computed = toml::from_str::<crate::core::SetuprsConfig>(
+            "[[computed]]
+name = 'crate_name'
+value = \"{{ project_name | lower | replace('-', '_') }}\"
+[[computed]]
+name = 'lib_path'
+value = 'src/{{ crate_name }}/lib.rs'",
+        )
+        .unwrap()
+        .computed
+        .unwrap();
+
+        let answers = Answers::from([("project_name".to_string(), Value::String("My-App".into()))]);
+        let answers = apply_computed(&answers, &computed, Path::new("setuprs.toml")).unwrap();
+
+        assert_eq!(answers["crate_name"], Value::String("my_app".into()));
+        assert_eq!(
+            answers["lib_path"],
+            Value::String("src/my_app/lib.rs".into())
+        );
+    }
+
+    #[test]
+    fn should_load_answers_from_recorded_answers_file() {
+        let folder = TestFolder::new();
+
+        let record = AnswersRecord {
+            snapshot_id: "snap_1".to_string(),
+            snapshot_version: Some(1),
+            snapshot_hash: "hash".to_string(),
+            source: None,
+            git_ref: None,
+            git_commit: None,
+            subdir: None,
+            answers: Answers::from([("port".to_string(), Value::Integer(8080))]),
+        };
+        record.write(folder.path()).unwrap();
+
+        let answers = load_answers_file(&folder.join(ANSWERS_FILE)).unwrap();
+        assert_eq!(answers, record.answers);
+    }
+
+    #[test]
+    fn should_reject_provided_answers_of_wrong_type() {
+        let provided = Answers::from([("port".to_string(), Value::String("http".into()))]);
+
+        assert!(resolve_answers(&variables(), &provided, true, |_| {
+            panic!("should not prompt")
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    collections::BTreeSet,
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+
+use super::{
+    manifest::SnapshotManifest,
+    store::{add_blob, hash_file, Tree},
+    validate_snapshot_id, Config,
+};
+
+const INDEX_FILE: &str = "setuprs-archive.toml";
+const FORMAT: u32 = 1;
+
+/// First entry of an archive, what it contains
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct ArchiveIndex {
+    format: u32,
+    id: String,
+    versions: Vec<u32>,
+}
+
+/// Packs every version of the snapshot `id`, their manifests and the blobs they use
+/// into a gzipped tar at `output`
+pub fn export(config: &Config, id: &str, output: &Path) -> Result<()> {
+    let versions = config.versions(id)?;
+
+    if versions.is_empty() {
+        return Err(match config.snapshot_path(id).exists() {
+            true => Error::UnversionedSnapshot(id.to_string()),
+            false => Error::SnapshotDontExist,
+        });
+    }
+
+    let mut archive = tar::Builder::new(GzEncoder::new(
+        File::create(output)?,
+        Compression::default(),
+    ));
+
+    let index = toml::to_string(&ArchiveIndex {
+        format: FORMAT,
+        id: id.to_string(),
+        versions: versions.clone(),
+    })?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(index.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, INDEX_FILE, index.as_bytes())?;
+
+    let mut blobs = BTreeSet::new();
+    for version in &versions {
+        let tree = Tree::load(config, id, *version)?;
+        blobs.extend(tree.entries.into_iter().filter_map(|entry| entry.blob));
+
+        archive.append_path_with_name(
+            config.tree_path(id, *version),
+            format!("trees/{version}.toml"),
+        )?;
+
+        let manifest = config.manifest_path(id, *version);
+        if manifest.exists() {
+            archive.append_path_with_name(manifest, format!("manifests/{version}.toml"))?;
+        }
+    }
+
+    for blob in blobs {
+        archive.append_path_with_name(config.blob_path(&blob), format!("objects/{blob}"))?;
+    }
+
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct ImportOptions<'a> {
+    /// Import the snapshot with this id instead of the one it was exported with
+    pub new_id: Option<&'a str>,
+    /// Replace an existing snapshot with the same id
+    pub force: bool,
+    /// Accept symlinks pointing outside the snapshot
+    pub allow_outside_symlinks: bool,
+}
+
+/// Adds the snapshot packed in `input`. Nothing is stored unless every blob matches its
+/// hash, every version has all its blobs and a valid manifest if any, and no entry would
+/// be written outside the folder it is checked out to. A replaced snapshot is only
+/// removed once the imported one is in place.
+pub fn import(config: &Config, input: &Path, options: &ImportOptions) -> Result<String> {
+    let unpacked = env::temp_dir().join(format!("setuprs-import-{}", Uuid::new_v4()));
+    let imported = unpack(input, &unpacked).and_then(|_| store(config, input, &unpacked, options));
+    let _ = fs::remove_dir_all(&unpacked);
+
+    imported
+}
+
+fn unpack(input: &Path, dst: &Path) -> Result<()> {
+    tar::Archive::new(GzDecoder::new(File::open(input)?))
+        .unpack(dst)
+        .map_err(|e| invalid(input, &e.to_string()))
+}
+
+fn store(
+    config: &Config,
+    input: &Path,
+    unpacked: &Path,
+    options: &ImportOptions,
+) -> Result<String> {
+    let index = fs::read_to_string(unpacked.join(INDEX_FILE))
+        .map_err(|_| invalid(input, &format!("missing {INDEX_FILE}")))?;
+    let index: ArchiveIndex = toml::from_str(&index)?;
+
+    if index.format != FORMAT {
+        return Err(invalid(
+            input,
+            &format!("unsupported format {}", index.format),
+        ));
+    }
+
+    let id = options.new_id.unwrap_or(&index.id).to_string();
+    validate_snapshot_id(&id)?;
+
+    let exists = config.snapshot_exists(&id)?;
+    if exists && !options.force {
+        return Err(Error::SnapshotAlreadyExists(id));
+    }
+
+    let objects = unpacked.join("objects");
+    let mut blobs = BTreeSet::new();
+    if objects.exists() {
+        for blob in fs::read_dir(&objects)? {
+            let blob = blob?;
+            let name = blob.file_name().to_string_lossy().to_string();
+
+            if hash_file(&blob.path())? != name {
+                return Err(invalid(input, &format!("corrupted file {name}")));
+            }
+            blobs.insert(name);
+        }
+    }
+
+    let mut trees = vec![];
+    for version in &index.versions {
+        let path = unpacked.join("trees").join(format!("{version}.toml"));
+        let tree: Tree = toml::from_str(
+            &fs::read_to_string(&path)
+                .map_err(|_| invalid(input, &format!("missing version {version}")))?,
+        )?;
+
+        tree.check(options.allow_outside_symlinks)
+            .map_err(|reason| invalid(input, &reason))?;
+
+        if let Some(missing) = tree
+            .entries
+            .iter()
+            .filter_map(|entry| entry.blob.as_ref())
+            .find(|blob| !blobs.contains(*blob))
+        {
+            return Err(invalid(input, &format!("missing file {missing}")));
+        }
+
+        let manifest = unpacked.join("manifests").join(format!("{version}.toml"));
+        let manifest = match manifest.exists() {
+            true => {
+                let manifest: SnapshotManifest = toml::from_str(&fs::read_to_string(manifest)?)?;
+                manifest.check().map_err(|reason| invalid(input, &reason))?;
+                Some(SnapshotManifest {
+                    id: id.clone(),
+                    version: *version,
+                    ..manifest
+                })
+            }
+            false => None,
+        };
+
+        trees.push((*version, path, manifest));
+    }
+
+    // Blobs first, a version is never visible before everything it uses is stored
+    for blob in &blobs {
+        add_blob(config, blob, &objects.join(blob))?;
+    }
+
+    let staging = Path::new(&config.snapshots_path).join(format!(".import-{}", Uuid::new_v4()));
+    let stored = stage(&staging, &trees).and_then(|_| swap_in(config, &id, &staging));
+
+    match stored {
+        Ok(()) => fs::remove_dir_all(&staging)?,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    }
+
+    Ok(id)
+}
+
+/// Writes the trees and manifests of the imported versions under `staging`, laid out as
+/// in the snapshots folder
+fn stage(staging: &Path, trees: &[(u32, PathBuf, Option<SnapshotManifest>)]) -> Result<()> {
+    fs::create_dir_all(staging.join("trees"))?;
+    fs::create_dir_all(staging.join("manifests"))?;
+
+    for (version, path, manifest) in trees {
+        fs::copy(path, staging.join("trees").join(format!("{version}.toml")))?;
+
+        if let Some(manifest) = manifest {
+            fs::write(
+                staging.join("manifests").join(format!("{version}.toml")),
+                toml::to_string(manifest)?,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the versions of `id` by the staged ones. What is replaced is moved aside
+/// first and put back if anything fails, it is deleted with the staging folder.
+fn swap_in(config: &Config, id: &str, staging: &Path) -> Result<()> {
+    let replaced = staging.join("replaced");
+    fs::create_dir_all(&replaced)?;
+
+    let current = [
+        ("trees", config.trees_path().join(id)),
+        ("manifests", config.manifests_path(id)),
+        ("legacy", config.snapshot_path(id)),
+    ];
+    let mut aside = vec![];
+    let mut placed = vec![];
+
+    let mut swap = || -> Result<()> {
+        for (name, path) in &current {
+            if path.exists() {
+                fs::rename(path, replaced.join(name))?;
+                aside.push((replaced.join(name), path));
+            }
+        }
+
+        for (name, path) in &current[..2] {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(staging.join(name), path)?;
+            placed.push(path);
+        }
+
+        Ok(())
+    };
+    let swapped = swap();
+
+    if swapped.is_err() {
+        for path in placed {
+            let _ = fs::remove_dir_all(path);
+        }
+        for (from, to) in aside {
+            let _ = fs::rename(from, to);
+        }
+    }
+
+    swapped
+}
+
+fn invalid(input: &Path, reason: &str) -> Error {
+    Error::InvalidArchive {
+        path: input.display().to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{
+        core::{
+            store::write_version,
+            testing::{test_config, TestFolder},
+        },
+        error::Error,
+    };
+
+    use super::{export, import, ImportOptions};
+
+    #[test]
+    fn should_export_and_import_every_version() {
+        let folder = TestFolder::new().add_file("project/main.rs", "v1");
+        let mine = test_config(folder.join("mine"));
+        let theirs = test_config(folder.join("theirs"));
+        let project = folder.join("project");
+        write_version(&mine, "tpl", 1, &project).unwrap();
+        fs::write(project.join("main.rs"), "v2").unwrap();
+        write_version(&mine, "tpl", 2, &project).unwrap();
+
+        let archive = folder.join("tpl.tar.gz");
+        export(&mine, "tpl", &archive).unwrap();
+
+        let imported = import(&theirs, &archive, &ImportOptions::default());
+        assert_eq!(imported.unwrap(), "tpl");
+        let again = import(&theirs, &archive, &ImportOptions::default());
+        assert!(matches!(again, Err(Error::SnapshotAlreadyExists(id)) if id == "tpl"));
+
+        let renamed = import(
+            &theirs,
+            &archive,
+            &ImportOptions {
+                new_id: Some("copy"),
+                ..Default::default()
+            },
+        );
+        assert_eq!(renamed.unwrap(), "copy");
+        let forced = import(
+            &theirs,
+            &archive,
+            &ImportOptions {
+                force: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(forced.unwrap(), "tpl");
+        assert_eq!(theirs.versions("copy").unwrap(), vec![1, 2]);
+
+        let missing = export(&mine, "other", &archive);
+        assert!(matches!(missing, Err(Error::SnapshotDontExist)));
+    }
+
+    #[test]
+    fn should_refuse_archive_with_corrupted_file() {
+        let folder = TestFolder::new().add_file("project/main.rs", "fn main() {}");
+        let mine = test_config(folder.join("mine"));
+        let theirs = test_config(folder.join("theirs"));
+        write_version(&mine, "tpl", 1, &folder.join("project")).unwrap();
+
+        // Same size so only the hash can tell
+        for prefix in fs::read_dir(mine.objects_path()).unwrap() {
+            for blob in fs::read_dir(prefix.unwrap().path()).unwrap() {
+                fs::write(blob.unwrap().path(), "fn evil() {").unwrap();
+            }
+        }
+
+        let archive = folder.join("tpl.tar.gz");
+        export(&mine, "tpl", &archive).unwrap();
+        let imported = import(&theirs, &archive, &ImportOptions::default());
+
+        assert!(matches!(imported, Err(Error::InvalidArchive { .. })));
+        assert!(!theirs.trees_path().exists() && !theirs.objects_path().exists());
+    }
+
+    #[test]
+    fn should_refuse_archive_writing_outside_the_snapshot() {
+        let folder = TestFolder::new();
+        let theirs = test_config(folder.join("theirs"));
+
+        let pack = |name: &str, files: &[(&str, &str)]| {
+            let path = folder.join(name);
+            let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+                fs::File::create(&path).unwrap(),
+                flate2::Compression::default(),
+            ));
+            let index = [(
+                "setuprs-archive.toml",
+                "format = 1\nid = 'evil'\nversions = [1]\n",
+            )];
+            for (entry, content) in index.iter().chain(files) {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                archive
+                    .append_data(&mut header, entry, content.as_bytes())
+                    .unwrap();
+            }
+            archive.into_inner().unwrap().finish().unwrap();
+            path
+        };
+
+        let escaping = pack(
+            "escaping.tar.gz",
+            &[(
+                "trees/1.toml",
+                "[[entry]]\npath = '../../escaped'\nkind = 'dir'\n",
+            )],
+        );
+        let linked = pack(
+            "linked.tar.gz",
+            &[(
+                "trees/1.toml",
+                "[[entry]]\npath = 'link'\nkind = 'symlink'\ntarget = '/tmp'\n",
+            )],
+        );
+        let broken_manifest = pack(
+            "broken_manifest.tar.gz",
+            &[
+                ("trees/1.toml", "[[entry]]\npath = 'folder'\nkind = 'dir'\n"),
+                (
+                    "manifests/1.toml",
+                    "id = 'evil'\nversion = 1\nfile_count = 0\ntotal_size = 0\ncontent_hash = 'abc'\n",
+                ),
+            ],
+        );
+
+        let escaped = import(&theirs, &escaping, &ImportOptions::default());
+        let refused = import(&theirs, &linked, &ImportOptions::default());
+        let allowed = import(
+            &theirs,
+            &linked,
+            &ImportOptions {
+                allow_outside_symlinks: true,
+                ..Default::default()
+            },
+        );
+
+        assert!(
+            matches!(escaped, Err(Error::InvalidArchive { reason, .. }) if reason.contains("invalid path"))
+        );
+        assert!(
+            matches!(refused, Err(Error::InvalidArchive { reason, .. }) if reason.contains("points outside"))
+        );
+        assert_eq!(allowed.unwrap(), "evil");
+
+        let replaced = import(
+            &theirs,
+            &broken_manifest,
+            &ImportOptions {
+                force: true,
+                ..Default::default()
+            },
+        );
+        let tree = fs::read_to_string(theirs.tree_path("evil", 1)).unwrap();
+
+        assert!(
+            matches!(replaced, Err(Error::InvalidArchive { reason, .. }) if reason.contains("invalid content hash"))
+        );
+        assert!(tree.contains("symlink"));
+        assert!(fs::read_dir(folder.join("theirs/snapshots"))
+            .unwrap()
+            .all(|entry| !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".import-")));
+    }
+}
@@ -0,0 +1,292 @@
+use std::{fmt::Display, fs, path::Path};
+
+use glob::Pattern;
+use serde_derive::Serialize;
+
+use crate::error::{Error, Result};
+
+use super::{
+    manifest::{count_files, SnapshotManifest},
+    store::Tree,
+    utils::get_all_snapshot_ids,
+    Config, SetuprsConfig,
+};
+
+/// One line of `snapshot show`, from the latest version of a snapshot
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SnapshotSummary {
+    pub id: String,
+    /// `None` for snapshots created before versions were stored
+    pub version: Option<u32>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub variables: usize,
+    pub size: u64,
+    pub updated_at: Option<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortKey {
+    #[default]
+    Id,
+    Updated,
+    Size,
+    Variables,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Aligned columns with a header
+    #[default]
+    Table,
+    /// Only the ids, one per line
+    Plain,
+    Json,
+}
+
+/// Which snapshots `list` keeps, every one when empty
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// All of them must be tags of the snapshot
+    pub tags: Vec<String>,
+    /// Matched against the id
+    pub glob: Option<String>,
+}
+
+/// Summaries of every snapshot matching `filter`, sorted by `sort`. Files and folders
+/// of the snapshots folder that aren't snapshots are left out.
+pub fn list(
+    config: &Config,
+    filter: &Filter,
+    sort: SortKey,
+    reverse: bool,
+) -> Result<Vec<SnapshotSummary>> {
+    let glob = filter
+        .glob
+        .as_ref()
+        .map(|glob| {
+            Pattern::new(glob).map_err(|e| Error::InvalidGlob {
+                glob: glob.clone(),
+                reason: e.to_string(),
+            })
+        })
+        .transpose()?;
+
+    let mut summaries = vec![];
+    for id in get_all_snapshot_ids(&config.snapshots_path)? {
+        if glob.as_ref().is_some_and(|glob| !glob.matches(&id)) {
+            continue;
+        }
+
+        let Some(summary) = SnapshotSummary::load(config, &id)? else {
+            continue;
+        };
+
+        if filter.tags.iter().all(|tag| summary.tags.contains(tag)) {
+            summaries.push(summary);
+        }
+    }
+
+    summaries.sort_by(|a, b| match sort {
+        SortKey::Id => a.id.cmp(&b.id),
+        SortKey::Updated => a.updated_at.cmp(&b.updated_at),
+        SortKey::Size => a.size.cmp(&b.size),
+        SortKey::Variables => a.variables.cmp(&b.variables),
+    });
+    if reverse {
+        summaries.reverse();
+    }
+
+    Ok(summaries)
+}
+
+impl SnapshotSummary {
+    /// `None` when `id` is not a snapshot
+    pub fn load(config: &Config, id: &str) -> Result<Option<Self>> {
+        if let Some(version) = config.versions(id)?.last().copied() {
+            let manifest = SnapshotManifest::load(config, id, version)?.unwrap_or_default();
+            let setuprs_toml = Tree::load(config, id, version)?
+                .entries
+                .into_iter()
+                .find(|entry| entry.path == "setuprs.toml")
+                .and_then(|entry| entry.blob)
+                .and_then(|blob| fs::read_to_string(config.blob_path(&blob)).ok());
+
+            return Ok(Some(Self {
+                id: id.to_string(),
+                version: Some(version),
+                description: manifest.description,
+                tags: manifest.tags,
+                variables: count_variables(setuprs_toml.as_deref()),
+                size: manifest.total_size,
+                updated_at: manifest.created_at,
+            }));
+        }
+
+        let path = config.snapshot_path(id);
+        if !path.is_dir() {
+            return Ok(None);
+        }
+
+        let project = SetuprsConfig::load(&path)
+            .ok()
+            .and_then(|config| config.project);
+        let setuprs_toml = fs::read_to_string(path.join("setuprs.toml")).ok();
+
+        Ok(Some(Self {
+            id: id.to_string(),
+            version: None,
+            description: project
+                .as_ref()
+                .and_then(|project| project.description.clone()),
+            tags: project.and_then(|project| project.tags).unwrap_or_default(),
+            variables: count_variables(setuprs_toml.as_deref()),
+            size: count_files(&path)?.1,
+            updated_at: modified_at(&path),
+        }))
+    }
+}
+
+/// A snapshot with a broken `setuprs.toml` is still listed, it fails when cloned
+fn count_variables(setuprs_toml: Option<&str>) -> usize {
+    setuprs_toml
+        .and_then(|content| toml::from_str::<SetuprsConfig>(content).ok())
+        .and_then(|config| config.variables)
+        .map_or(0, |variables| variables.len())
+}
+
+fn modified_at(path: &Path) -> Option<String> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(humantime::format_rfc3339_seconds(modified).to_string())
+}
+
+/// Every summary as a table, columns padded to their widest value
+pub struct Table<'a>(pub &'a [SnapshotSummary]);
+
+impl Display for Table<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = [
+            "ID",
+            "VERSION",
+            "DESCRIPTION",
+            "TAGS",
+            "VARIABLES",
+            "SIZE",
+            "UPDATED",
+        ]
+        .map(String::from);
+
+        let rows = self.0.iter().map(|summary| {
+            [
+                summary.id.clone(),
+                summary
+                    .version
+                    .map_or("-".to_string(), |version| version.to_string()),
+                summary.description.clone().unwrap_or("-".to_string()),
+                match summary.tags.is_empty() {
+                    true => "-".to_string(),
+                    false => summary.tags.join(", "),
+                },
+                summary.variables.to_string(),
+                summary.size.to_string(),
+                summary.updated_at.clone().unwrap_or("-".to_string()),
+            ]
+        });
+        let rows = std::iter::once(header).chain(rows).collect::<Vec<_>>();
+
+        let mut widths = [0; 7];
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        for row in &rows {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::core::{
+        manifest::SnapshotManifest,
+        store::write_version,
+        testing::{test_config, TestFolder},
+    };
+
+    use super::{list, Filter, SortKey, Table};
+
+    #[test]
+    fn should_list_filter_and_sort_snapshots() {
+        let folder = TestFolder::new().add_file(
+            "project/setuprs.toml",
+            "[[variables]]\nname = 'a'\n[[variables]]\nname = 'b'",
+        );
+        let config = test_config(folder.path());
+        let project = folder.join("project");
+        for (id, tags, size) in [("web", vec!["rust", "web"], 10), ("cli", vec!["rust"], 1)] {
+            write_version(&config, id, 1, &project).unwrap();
+            SnapshotManifest {
+                id: id.to_string(),
+                version: 1,
+                tags: tags.into_iter().map(String::from).collect(),
+                total_size: size,
+                content_hash: "0".repeat(64),
+                ..Default::default()
+            }
+            .write(&config)
+            .unwrap();
+        }
+        fs::create_dir_all(config.snapshot_path("legacy")).unwrap();
+        fs::write(config.snapshot_path("stray.txt"), "").unwrap();
+
+        let ids = |filter: Filter, sort: SortKey, reverse: bool| {
+            list(&config, &filter, sort, reverse)
+                .unwrap()
+                .into_iter()
+                .map(|summary| summary.id)
+                .collect::<Vec<_>>()
+        };
+
+        let all = ids(Filter::default(), SortKey::Id, false);
+        let by_size = ids(Filter::default(), SortKey::Size, true);
+        let tagged = ids(
+            Filter {
+                tags: vec!["web".to_string()],
+                glob: None,
+            },
+            SortKey::Id,
+            false,
+        );
+        let globbed = ids(
+            Filter {
+                tags: vec![],
+                glob: Some("c*".to_string()),
+            },
+            SortKey::Id,
+            false,
+        );
+        let table =
+            Table(&list(&config, &Filter::default(), SortKey::Id, false).unwrap()).to_string();
+
+        assert_eq!(all, vec!["cli", "legacy", "web"]);
+        assert_eq!(by_size, vec!["web", "cli", "legacy"]);
+        assert_eq!(tagged, vec!["web"]);
+        assert_eq!(globbed, vec!["cli"]);
+        assert!(table.starts_with(
+            "ID      VERSION  DESCRIPTION  TAGS       VARIABLES  SIZE  UPDATED\n\
+             cli     1        -            rust       2          1     -\n"
+        ));
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+
+use super::{
+    preview::preview_clone,
+    utils::{create_symlink, write_sections},
+};
+
+/// What a clone does with files of the destination the snapshot would change
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConflictPolicy {
+    /// Write nothing and list them
+    #[default]
+    Fail,
+    /// Keep them as they are
+    Skip,
+    /// Replace them
+    Overwrite,
+    /// Ask for each of them
+    Prompt,
+    /// Replace them, keeping the original next to it as `<file>.bak`
+    Backup,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CloneReport {
+    pub created: Vec<String>,
+    pub replaced: Vec<String>,
+    /// Original file and where it was moved
+    pub backed_up: Vec<(String, String)>,
+    pub skipped: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+impl Display for CloneReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backed_up = self
+            .backed_up
+            .iter()
+            .map(|(file, backup)| format!("{file} (original in {backup})"))
+            .collect::<Vec<_>>();
+
+        write_sections(
+            f,
+            &[
+                ("Replaced", &self.replaced),
+                ("Replaced, backed up", &backed_up),
+                ("Skipped, modified locally", &self.skipped),
+            ],
+        )
+    }
+}
+
+/// Moves the files of `rendered` into `destination`. Files the destination already has
+/// with other content are handled by `policy`, `confirm` answering for each of them
+/// with `ConflictPolicy::Prompt`. Every conflict is settled before anything is written,
+/// and when a file can't be written everything done so far is undone.
+pub fn install<F>(
+    rendered: &Path,
+    destination: &Path,
+    policy: ConflictPolicy,
+    mut confirm: F,
+) -> Result<CloneReport>
+where
+    F: FnMut(&str) -> bool,
+{
+    let preview = preview_clone(rendered, destination)?;
+    let mut report = CloneReport {
+        created: preview.created,
+        unchanged: preview.unchanged,
+        ..Default::default()
+    };
+
+    for file in preview.overwritten {
+        match policy {
+            ConflictPolicy::Fail | ConflictPolicy::Skip => report.skipped.push(file),
+            ConflictPolicy::Overwrite => report.replaced.push(file),
+            ConflictPolicy::Prompt if confirm(&file) => report.replaced.push(file),
+            ConflictPolicy::Prompt => report.skipped.push(file),
+            ConflictPolicy::Backup => {
+                let backup = backup_name(destination, &file);
+                report.backed_up.push((file, backup));
+            }
+        }
+    }
+
+    if policy == ConflictPolicy::Fail && !report.skipped.is_empty() {
+        return Err(Error::CloneConflict(report.skipped));
+    }
+
+    let mut journal = Journal::default();
+    let installed = apply(rendered, destination, &report, &mut journal);
+
+    match installed {
+        Ok(_) => journal.commit()?,
+        Err(e) => {
+            journal.rollback();
+            return Err(e);
+        }
+    }
+
+    Ok(report)
+}
+
+/// What `install` changed in the destination so far, in order
+#[derive(Default)]
+struct Journal {
+    /// Created folders with the rendered folder to take the mode and mtime from, none
+    /// for the missing parents of the destination
+    created_dirs: Vec<(PathBuf, Option<PathBuf>)>,
+    created_files: Vec<PathBuf>,
+    /// Files moved to their backup, left there once installed
+    backed_up: Vec<(PathBuf, PathBuf)>,
+    /// Replaced files moved out of the way, removed once installed
+    set_aside: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Journal {
+    /// Folder modes are applied last, a read only folder would refuse its files otherwise
+    fn commit(self) -> Result<()> {
+        for (_, aside) in &self.set_aside {
+            match aside.symlink_metadata()?.is_dir() {
+                true => fs::remove_dir_all(aside)?,
+                false => fs::remove_file(aside)?,
+            }
+        }
+
+        for (dst, src) in self.created_dirs.iter().rev() {
+            let Some(src) = src else {
+                continue;
+            };
+
+            let metadata = fs::metadata(src)?;
+            fs::set_permissions(dst, metadata.permissions())?;
+            fs::File::open(dst)?.set_modified(metadata.modified()?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Best effort, the error that stopped the install is the one worth reporting
+    fn rollback(self) {
+        for file in self.created_files.iter().rev() {
+            let _ = fs::remove_file(file);
+        }
+
+        for (original, moved) in self.set_aside.iter().chain(&self.backed_up).rev() {
+            let _ = fs::rename(moved, original);
+        }
+
+        for (dir, _) in self.created_dirs.iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+fn apply(
+    rendered: &Path,
+    destination: &Path,
+    report: &CloneReport,
+    journal: &mut Journal,
+) -> Result<()> {
+    // Missing parents of the destination are removed on rollback too
+    let missing = destination
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .collect::<Vec<_>>();
+    for dir in missing.into_iter().rev() {
+        fs::create_dir(dir)?;
+        journal.created_dirs.push((dir.to_path_buf(), None));
+    }
+
+    for (file, backup) in &report.backed_up {
+        let (original, backup) = (destination.join(file), destination.join(backup));
+        fs::rename(&original, &backup)?;
+        journal.backed_up.push((original, backup));
+    }
+
+    for file in &report.replaced {
+        let original = destination.join(file);
+        let mut aside = original.clone().into_os_string();
+        aside.push(format!(".setuprs-{}", Uuid::new_v4()));
+
+        fs::rename(&original, &aside)?;
+        journal.set_aside.push((original, aside.into()));
+    }
+
+    let left_out = report
+        .skipped
+        .iter()
+        .chain(&report.unchanged)
+        .map(PathBuf::from)
+        .collect();
+
+    move_dir(rendered, destination, Path::new(""), &left_out, journal)
+}
+
+/// First `<file>.bak`, `<file>.bak.1`, ... not taken in `destination`
+fn backup_name(destination: &Path, file: &str) -> String {
+    let mut backup = format!("{file}.bak");
+    let mut n = 1;
+
+    while destination.join(&backup).symlink_metadata().is_ok() {
+        backup = format!("{file}.bak.{n}");
+        n += 1;
+    }
+
+    backup
+}
+
+fn move_dir(
+    rendered: &Path,
+    destination: &Path,
+    relative: &Path,
+    left_out: &BTreeSet<PathBuf>,
+    journal: &mut Journal,
+) -> Result<()> {
+    let src = rendered.join(relative);
+    let dst = destination.join(relative);
+
+    if !dst.exists() {
+        fs::create_dir(&dst)?;
+        journal.created_dirs.push((dst, Some(src.clone())));
+    }
+
+    let mut entries = fs::read_dir(&src)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let relative = relative.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            move_dir(rendered, destination, &relative, left_out, journal)?;
+        } else if !left_out.contains(&relative) {
+            let target = destination.join(&relative);
+            journal.created_files.push(target.clone());
+            move_entry(&entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Renamed when both sides are on the same filesystem, copied otherwise
+fn move_entry(src: &Path, dst: &Path) -> Result<()> {
+    if fs::rename(src, dst).is_ok() {
+        return Ok(());
+    }
+
+    let metadata = src.symlink_metadata()?;
+    if metadata.is_symlink() {
+        create_symlink(&fs::read_link(src)?, dst)?;
+    } else {
+        fs::copy(src, dst)?;
+        fs::File::open(dst)?.set_modified(metadata.modified()?)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::{core::testing::TestFolder, error::Error};
+
+    use super::{install, ConflictPolicy};
+
+    /// A rendered tree and a destination sharing a modified, an unchanged and a new file
+    fn setup() -> TestFolder {
+        TestFolder::new()
+            .add_file("rendered/src/main.rs", "fn main() {}")
+            .add_file("rendered/README.md", "# app")
+            .add_file("rendered/LICENSE", "MIT")
+            .add_file("destination/README.md", "# mine")
+            .add_file("destination/LICENSE", "MIT")
+    }
+
+    #[test]
+    fn should_apply_every_conflict_policy() {
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+
+        let folder = setup();
+        let (rendered, destination) = (folder.join("rendered"), folder.join("destination"));
+        let failed = install(&rendered, &destination, ConflictPolicy::Fail, |_| true);
+        assert!(matches!(failed, Err(Error::CloneConflict(files)) if files == ["README.md"]));
+        assert!(!destination.join("src").exists());
+        assert_eq!(read(&destination.join("README.md")), "# mine");
+
+        let skipped = install(&rendered, &destination, ConflictPolicy::Skip, |_| true).unwrap();
+        assert_eq!(skipped.skipped, vec!["README.md"]);
+        assert_eq!(skipped.created, vec!["src/main.rs"]);
+        assert_eq!(skipped.unchanged, vec!["LICENSE"]);
+        assert_eq!(read(&destination.join("README.md")), "# mine");
+        assert_eq!(read(&destination.join("src/main.rs")), "fn main() {}");
+
+        let folder = setup();
+        let (rendered, destination) = (folder.join("rendered"), folder.join("destination"));
+        let backed_up = install(&rendered, &destination, ConflictPolicy::Backup, |_| true).unwrap();
+        assert_eq!(
+            backed_up.backed_up,
+            vec![("README.md".to_string(), "README.md.bak".to_string())]
+        );
+        assert_eq!(read(&destination.join("README.md")), "# app");
+        assert_eq!(read(&destination.join("README.md.bak")), "# mine");
+        assert_eq!(
+            backed_up.to_string(),
+            "Replaced, backed up:\n  README.md (original in README.md.bak)\n"
+        );
+
+        let folder = setup();
+        let (rendered, destination) = (folder.join("rendered"), folder.join("destination"));
+        let declined = install(&rendered, &destination, ConflictPolicy::Prompt, |_| false).unwrap();
+        assert_eq!(declined.skipped, vec!["README.md"]);
+    }
+
+    #[test]
+    fn should_undo_everything_when_a_file_cant_be_written() {
+        // Written last, a file of the destination stands where its folder should be
+        let folder = setup()
+            .add_file("rendered/zzz/file.txt", "")
+            .add_file("destination/zzz", "");
+        let (rendered, destination) = (folder.join("rendered"), folder.join("destination"));
+
+        let failed = install(&rendered, &destination, ConflictPolicy::Backup, |_| true);
+        let mut left = fs::read_dir(&destination)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        left.sort();
+
+        assert!(failed.is_err());
+        assert_eq!(left, vec!["LICENSE", "README.md", "zzz"]);
+        assert_eq!(
+            fs::read_to_string(destination.join("README.md")).unwrap(),
+            "# mine"
+        );
+    }
+}
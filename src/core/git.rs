@@ -0,0 +1,221 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::error::{Error, Result};
+
+/// Files in the git index of the repository containing `path`, relative to `path`.
+/// The index is read directly, git itself doesn't need to be installed.
+pub fn tracked_files(path: &Path) -> Result<BTreeSet<PathBuf>> {
+    let path = fs::canonicalize(path)?;
+    let (work_tree, git_dir) =
+        find_git_dir(&path).ok_or_else(|| Error::NotAGitRepository(path.display().to_string()))?;
+
+    let prefix = path.strip_prefix(&work_tree).unwrap_or(Path::new(""));
+
+    Ok(read_index(&git_dir.join("index"))?
+        .into_iter()
+        .filter_map(|file| file.strip_prefix(prefix).ok().map(Path::to_path_buf))
+        .collect())
+}
+
+/// Runs the git binary, returning what it printed on stdout and failing with what it
+/// printed on stderr
+pub fn run_git(args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| Error::GitFailed(format!("failed to run git: {e}")))?;
+
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        false => Err(Error::GitFailed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+/// Work tree and git folder of the closest repository, `.git` being a folder or a
+/// `gitdir: <path>` file as in worktrees and submodules
+fn find_git_dir(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    for dir in path.ancestors() {
+        let dot_git = dir.join(".git");
+
+        if dot_git.is_dir() {
+            return Some((dir.to_path_buf(), dot_git));
+        }
+
+        if let Some(git_dir) = fs::read_to_string(&dot_git)
+            .ok()
+            .and_then(|content| Some(content.strip_prefix("gitdir:")?.trim().to_string()))
+        {
+            return Some((dir.to_path_buf(), dir.join(git_dir)));
+        }
+    }
+
+    None
+}
+
+/// Paths of every entry in a version 2, 3 or 4 index file
+fn read_index(index: &Path) -> Result<BTreeSet<PathBuf>> {
+    // Nothing was ever added to a fresh repository
+    if !index.exists() {
+        return Ok(BTreeSet::new());
+    }
+
+    let data = fs::read(index)?;
+    let invalid = |reason: &str| Error::InvalidGitIndex {
+        path: index.display().to_string(),
+        reason: reason.to_string(),
+    };
+
+    if data.len() < 12 || &data[..4] != b"DIRC" {
+        return Err(invalid("missing DIRC signature"));
+    }
+
+    let version = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+    let count = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+
+    if !(2..=4).contains(&version) {
+        return Err(invalid(&format!("unsupported version {version}")));
+    }
+
+    let mut files = BTreeSet::new();
+    let mut previous: Vec<u8> = vec![];
+    let mut offset = 12;
+
+    for _ in 0..count {
+        let entry_start = offset;
+        // ctime, mtime, dev, ino, mode, uid, gid, size and the object id
+        offset += 40 + 20;
+
+        let flags = data
+            .get(offset..offset + 2)
+            .map(|flags| u16::from_be_bytes([flags[0], flags[1]]))
+            .ok_or_else(|| invalid("truncated entry"))?;
+        offset += 2;
+
+        if version >= 3 && flags & 0x4000 != 0 {
+            offset += 2;
+        }
+
+        let mut name = match version {
+            // Prefix compressed, the entry only keeps what differs from the previous one
+            4 => {
+                let (strip, read) = read_varint(data.get(offset..).unwrap_or_default())
+                    .ok_or_else(|| invalid("truncated entry"))?;
+                offset += read;
+                previous.truncate(previous.len().saturating_sub(strip));
+                previous.clone()
+            }
+            _ => vec![],
+        };
+
+        let end = data
+            .get(offset..)
+            .and_then(|rest| rest.iter().position(|byte| *byte == 0))
+            .ok_or_else(|| invalid("truncated entry"))?;
+        name.extend_from_slice(&data[offset..offset + end]);
+        offset += end + 1;
+
+        // Entries are padded with NULs to a multiple of 8 bytes before version 4
+        if version < 4 {
+            let length = offset - entry_start;
+            offset = entry_start + length.next_multiple_of(8);
+        }
+
+        files.insert(PathBuf::from(String::from_utf8_lossy(&name).to_string()));
+        previous = name;
+    }
+
+    Ok(files)
+}
+
+/// Git's offset encoding, each continuation byte adds one before shifting
+fn read_varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut bytes = data.iter();
+    let mut byte = *bytes.next()?;
+    let mut value = (byte & 0x7f) as usize;
+    let mut read = 1;
+
+    while byte & 0x80 != 0 {
+        byte = *bytes.next()?;
+        value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+        read += 1;
+    }
+
+    Some((value, read))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        process::Command,
+    };
+
+    use crate::core::testing::TestFolder;
+
+    use super::tracked_files;
+
+    /// Repository holding `files`, each one containing its own name
+    fn repo(files: &[&str]) -> TestFolder {
+        let repo = files.iter().fold(TestFolder::new(), |folder, name| {
+            folder.add_file(name, name)
+        });
+        git(&repo, &["init", "-q"]);
+        repo
+    }
+
+    fn git(repo: &TestFolder, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(repo.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn should_list_tracked_files_of_every_index_version() {
+        let repo = repo(&[
+            "setuprs.toml",
+            "src/main.rs",
+            "src/module/a_long_enough_name.rs",
+            "src/module/a_long_enough_name_too.rs",
+            "untracked.txt",
+        ]);
+        git(
+            &repo,
+            &[
+                "add",
+                "setuprs.toml",
+                "src/main.rs",
+                "src/module/a_long_enough_name.rs",
+                "src/module/a_long_enough_name_too.rs",
+            ],
+        );
+
+        let expected = [
+            "setuprs.toml",
+            "src/main.rs",
+            "src/module/a_long_enough_name.rs",
+            "src/module/a_long_enough_name_too.rs",
+        ]
+        .map(PathBuf::from)
+        .into_iter()
+        .collect();
+
+        for version in ["2", "3", "4"] {
+            git(&repo, &["update-index", "--index-version", version]);
+            assert_eq!(tracked_files(repo.path()).unwrap(), expected);
+        }
+
+        let in_subfolder = tracked_files(&repo.join("src")).unwrap();
+        assert!(in_subfolder.contains(Path::new("main.rs")));
+        assert_eq!(in_subfolder.len(), 3);
+    }
+}
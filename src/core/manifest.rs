@@ -0,0 +1,179 @@
+use std::{fmt::Display, fs, path::Path};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+use super::{
+    utils::{hash_dir, walk},
+    Config,
+};
+
+/// What is known about a snapshot besides its files
+#[derive(PartialEq, Serialize, Deserialize, Debug, Default)]
+pub struct SnapshotManifest {
+    pub id: String,
+    /// 0 for snapshots created before versioning
+    pub version: u32,
+    pub created_at: Option<String>,
+    pub source_path: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub file_count: u64,
+    pub total_size: u64,
+    pub content_hash: String,
+    pub setuprs_version: Option<String>,
+}
+
+impl SnapshotManifest {
+    /// Counts, sizes and hashes the files of a snapshot, everything else is left empty
+    pub fn scan(id: &str, version: u32, snapshot_path: &Path) -> Result<Self> {
+        let (file_count, total_size) = count_files(snapshot_path)?;
+
+        Ok(Self {
+            id: id.to_string(),
+            version,
+            file_count,
+            total_size,
+            content_hash: hash_dir(snapshot_path)?,
+            ..Default::default()
+        })
+    }
+
+    /// The stored manifest, `None` for snapshots created before manifests existed
+    pub fn load(config: &Config, id: &str, version: u32) -> Result<Option<Self>> {
+        let path = config.manifest_path(id, version);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let manifest: Self = toml::from_str(&fs::read_to_string(path)?)?;
+        manifest
+            .check()
+            .map_err(|reason| Error::CorruptedSnapshot {
+                snapshot: format!("{id}@{version}"),
+                reason,
+            })?;
+
+        Ok(Some(manifest))
+    }
+
+    /// Refuses a manifest whose content hash isn't a sha256 as `hash_dir` writes it,
+    /// imported and pulled manifests aren't written by this setuprs, the reason otherwise
+    pub fn check(&self) -> std::result::Result<(), String> {
+        let hash = &self.content_hash;
+
+        match hash.len() == 64
+            && hash
+                .bytes()
+                .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+        {
+            true => Ok(()),
+            false => Err(format!("invalid content hash {hash}")),
+        }
+    }
+
+    pub fn write(&self, config: &Config) -> Result<()> {
+        let path = config.manifest_path(&self.id, self.version);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+impl Display for SnapshotManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tags = self.tags.join(", ");
+        let optional = [
+            ("Description", self.description.as_deref()),
+            ("Author", self.author.as_deref()),
+            ("Tags", Some(tags.as_str()).filter(|tags| !tags.is_empty())),
+            ("Created at", self.created_at.as_deref()),
+            ("Source", self.source_path.as_deref()),
+        ];
+
+        match self.version {
+            0 => writeln!(f, "Snapshot: {}", self.id)?,
+            version => writeln!(f, "Snapshot: {}@{version}", self.id)?,
+        }
+        for (title, value) in optional {
+            if let Some(value) = value {
+                writeln!(f, "{title}: {value}")?;
+            }
+        }
+        writeln!(f, "Files: {}", self.file_count)?;
+        writeln!(f, "Total size: {} bytes", self.total_size)?;
+        writeln!(f, "Content hash: {}", self.content_hash)?;
+        if let Some(version) = &self.setuprs_version {
+            writeln!(f, "Setuprs version: {version}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of files under `dir` and their total size, without hashing them
+pub fn count_files(dir: &Path) -> Result<(u64, u64)> {
+    let (mut count, mut size) = (0, 0);
+
+    for (_, metadata) in walk(dir)? {
+        if !metadata.is_dir() {
+            count += 1;
+            size += metadata.len();
+        }
+    }
+
+    Ok((count, size))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::core::testing::{test_config, TestFolder};
+
+    use super::SnapshotManifest;
+
+    #[test]
+    fn should_scan_snapshot_and_keep_manifest_apart() {
+        let folder = TestFolder::new()
+            .add_file("snapshot/setuprs.toml", "[project]\nname='a'")
+            .add_file("snapshot/src/main.rs", "fn main() {}");
+        let snapshot_path = folder.join("snapshot");
+        let config = test_config(folder.path());
+
+        let manifest = SnapshotManifest {
+            tags: vec!["rust".to_string()],
+            ..SnapshotManifest::scan("snapshot", 1, &snapshot_path).unwrap()
+        };
+        manifest.write(&config).unwrap();
+
+        assert_eq!(manifest.file_count, 2);
+        assert_eq!(manifest.total_size, 30);
+        assert_eq!(
+            SnapshotManifest::load(&config, "snapshot", 1).unwrap(),
+            Some(manifest)
+        );
+        assert_eq!(
+            SnapshotManifest::load(&config, "snapshot", 2).unwrap(),
+            None
+        );
+        assert_eq!(fs::read_dir(&snapshot_path).unwrap().count(), 2);
+
+        SnapshotManifest {
+            version: 3,
+            content_hash: "abc".to_string(),
+            ..Default::default()
+        }
+        .write(&config)
+        .unwrap();
+        assert!(SnapshotManifest::load(&config, "", 3).is_err());
+    }
+}
@@ -1,13 +1,39 @@
-use std::{env, fmt::Display, fs, path::PathBuf, str::FromStr};
+use std::{
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use serde_derive::Deserialize;
+pub mod answers;
+pub mod archive;
+pub mod catalog;
+pub mod clone;
+pub mod git;
+pub mod manifest;
+pub mod preview;
+pub mod registry;
+pub mod source;
+pub mod store;
+pub mod template;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod update;
 pub mod utils;
+mod variables;
+
+pub use registry::Registry;
+pub use variables::Variables;
 
 #[derive(PartialEq, Deserialize, Debug)]
 pub struct Config {
     pub config_file_path: String,
     pub debug_mode: String,
     pub snapshots_path: String,
+    #[serde(default)]
+    pub registries: Vec<Registry>,
 }
 
 impl Config {
@@ -18,15 +44,121 @@ impl Config {
         };
 
         if let Ok(contents) = fs::read_to_string(config_path) {
-            if let Ok(newconf) = toml::from_str::<Config>(&contents) {
-                newconf
-            } else {
-                Config::default()
-            }
+            toml::from_str::<Config>(&contents).unwrap_or_default()
         } else {
             Config::default()
         }
     }
+
+    /// Folder of a snapshot created before versions were stored
+    pub fn snapshot_path(&self, id: &str) -> PathBuf {
+        Path::new(&self.snapshots_path).join(id)
+    }
+
+    /// Metadata of the versions of a snapshot, kept apart so it never reaches the clones
+    pub fn manifests_path(&self, id: &str) -> PathBuf {
+        Path::new(&self.snapshots_path).join(".manifests").join(id)
+    }
+
+    pub fn manifest_path(&self, id: &str, version: u32) -> PathBuf {
+        self.manifests_path(id).join(format!("{version}.toml"))
+    }
+
+    /// Content addressed files shared by every snapshot and version
+    pub fn objects_path(&self) -> PathBuf {
+        Path::new(&self.snapshots_path).join(".objects")
+    }
+
+    /// Stored file with the sha256 `hash`, fanned out by its first two characters
+    pub fn blob_path(&self, hash: &str) -> PathBuf {
+        let (prefix, rest) = hash.split_at(hash.len().min(2));
+        self.objects_path().join(prefix).join(rest)
+    }
+
+    pub fn trees_path(&self) -> PathBuf {
+        Path::new(&self.snapshots_path).join(".trees")
+    }
+
+    /// Entries of a created version of a snapshot, never modified afterwards
+    pub fn tree_path(&self, id: &str, version: u32) -> PathBuf {
+        self.trees_path().join(id).join(format!("{version}.toml"))
+    }
+
+    /// Every version of a snapshot, oldest first
+    pub fn versions(&self, id: &str) -> crate::error::Result<Vec<u32>> {
+        let trees = self.trees_path().join(id);
+
+        if !trees.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut versions = fs::read_dir(trees)?
+            .filter_map(|entry| {
+                entry
+                    .ok()?
+                    .file_name()
+                    .to_str()?
+                    .strip_suffix(".toml")?
+                    .parse::<u32>()
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+        versions.sort();
+
+        Ok(versions)
+    }
+
+    /// Whether there is a snapshot `id`, with versions or created before them
+    pub fn snapshot_exists(&self, id: &str) -> crate::error::Result<bool> {
+        Ok(!self.versions(id)?.is_empty() || self.snapshot_path(id).exists())
+    }
+
+    pub fn registry(&self, name: &str) -> crate::error::Result<&Registry> {
+        self.registries
+            .iter()
+            .find(|registry| registry.name == name)
+            .ok_or_else(|| crate::error::Error::UnknownRegistry(name.to_string()))
+    }
+
+    /// Id and version of a `id@version` reference, the latest version when none is given.
+    /// The version is `None` for snapshots created before versioning, still stored as a
+    /// plain folder.
+    pub fn resolve_snapshot(&self, reference: &str) -> crate::error::Result<(String, Option<u32>)> {
+        let (id, version) = parse_snapshot_reference(reference)?;
+
+        match version {
+            Some(version) if self.tree_path(&id, version).exists() => Ok((id, Some(version))),
+            Some(_) => Err(crate::error::Error::SnapshotVersionDontExist(
+                reference.to_string(),
+            )),
+            None => match self.versions(&id)?.last() {
+                Some(latest) => Ok((id, Some(*latest))),
+                None if self.snapshot_path(&id).exists() => Ok((id, None)),
+                None => Err(crate::error::Error::SnapshotDontExist),
+            },
+        }
+    }
+}
+
+/// Ids end up as folder names and in `id@version` references
+pub fn validate_snapshot_id(id: &str) -> crate::error::Result<()> {
+    match id.is_empty() || id.starts_with('.') || id.contains(['/', '\\', '@']) {
+        true => Err(crate::error::Error::InvalidSnapshotId(id.to_string())),
+        false => Ok(()),
+    }
+}
+
+/// Splits a `id@version` reference, no version meaning the latest one
+fn parse_snapshot_reference(reference: &str) -> crate::error::Result<(String, Option<u32>)> {
+    match reference.rsplit_once('@') {
+        Some((id, version)) => match version.parse::<u32>() {
+            Ok(version) => Ok((id.to_string(), Some(version))),
+            Err(_) => Err(crate::error::Error::SnapshotVersionDontExist(
+                reference.to_string(),
+            )),
+        },
+        None => Ok((reference.to_string(), None)),
+    }
 }
 
 impl Default for Config {
@@ -37,6 +169,7 @@ impl Default for Config {
             config_file_path: format!("{home}/.config/setuprs/setuprs.toml"),
             debug_mode: "error".to_string(),
             snapshots_path: format!("{home}/.config/setuprs/snapshots/"),
+            registries: vec![],
         }
     }
 }
@@ -81,25 +214,48 @@ impl FromStr for Config {
             config_file_path: config_file_path.unwrap(),
             snapshots_path: snapshot_path.unwrap(),
             debug_mode: debug_mode.unwrap(),
+            registries: vec![],
         })
     }
 }
 
-#[derive(PartialEq, Deserialize, Debug)]
+#[derive(PartialEq, Deserialize, Debug, Default)]
 pub struct SetuprsConfig {
     pub project: Option<Project>,
     pub variables: Option<Vec<Variables>>,
+    pub computed: Option<Vec<Computed>>,
+    pub binary_globs: Option<Vec<String>>,
+    /// Text files copied without rendering, like CI workflows using `${{ }}` themselves
+    pub exclude_render: Option<Vec<String>>,
+}
+
+impl SetuprsConfig {
+    /// Reads the `setuprs.toml` of a project or snapshot, empty when there is none
+    pub fn load(dir: impl AsRef<Path>) -> crate::error::Result<Self> {
+        let path = dir.as_ref().join("setuprs.toml");
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)?;
+        Ok(toml::from_str::<SetuprsConfig>(&content)?)
+    }
 }
 
 #[derive(PartialEq, Deserialize, Debug)]
 pub struct Project {
     pub name: String,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub tags: Option<Vec<String>>,
 }
 
+/// Variable derived from the answers, `value` is a template rendered after prompting
 #[derive(PartialEq, Deserialize, Debug)]
-pub struct Variables {
+pub struct Computed {
     pub name: String,
-    pub default: Option<String>,
+    pub value: String,
 }
 
 #[test]
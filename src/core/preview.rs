@@ -0,0 +1,144 @@
+use std::{fmt::Display, fs, path::Path};
+
+use diffy::DiffOptions;
+
+use crate::error::Result;
+
+use super::utils::{is_binary, walk, write_sections};
+
+/// What cloning a rendered snapshot into a destination would do to each of its files
+#[derive(Debug, Default, PartialEq)]
+pub struct ClonePreview {
+    pub created: Vec<String>,
+    pub overwritten: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+impl Display for ClonePreview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_sections(
+            f,
+            &[
+                ("Create", &self.created),
+                ("Overwrite", &self.overwritten),
+                ("Unchanged", &self.unchanged),
+            ],
+        )?;
+
+        writeln!(
+            f,
+            "{} to create, {} to overwrite, {} unchanged",
+            self.created.len(),
+            self.overwritten.len(),
+            self.unchanged.len()
+        )
+    }
+}
+
+/// Compares every file and symlink of `rendered` with what `destination` holds at the
+/// same path, without touching either
+pub fn preview_clone(rendered: &Path, destination: &Path) -> Result<ClonePreview> {
+    let mut preview = ClonePreview::default();
+
+    let files = walk(rendered)?
+        .into_iter()
+        .filter(|(_, metadata)| !metadata.is_dir());
+
+    for (file, metadata) in files {
+        let name = file.display().to_string();
+        let source = rendered.join(&file);
+        let target = destination.join(&file);
+
+        let Ok(existing) = target.symlink_metadata() else {
+            preview.created.push(name);
+            continue;
+        };
+
+        let same = match metadata.is_symlink() {
+            true => existing.is_symlink() && fs::read_link(&source)? == fs::read_link(&target)?,
+            false => existing.is_file() && fs::read(&source)? == fs::read(&target)?,
+        };
+
+        match same {
+            true => preview.unchanged.push(name),
+            false => preview.overwritten.push(name),
+        }
+    }
+
+    Ok(preview)
+}
+
+/// Unified diff of each of `files` from `destination` to `rendered`. Binary files and
+/// symlinks are only named.
+pub fn diff_files(rendered: &Path, destination: &Path, files: &[String]) -> Result<String> {
+    let mut diff = String::new();
+
+    for file in files {
+        let original = read_text(&destination.join(file));
+        let modified = read_text(&rendered.join(file));
+
+        match (original, modified) {
+            (Some(original), Some(modified)) => {
+                let patch = DiffOptions::new()
+                    .set_original_filename(format!("a/{file}"))
+                    .set_modified_filename(format!("b/{file}"))
+                    .create_patch(&original, &modified)
+                    .to_string();
+                diff.push_str(&patch);
+            }
+            _ => diff.push_str(&format!("Binary files a/{file} and b/{file} differ\n")),
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Content of a regular text file, `None` for anything else
+fn read_text(path: &Path) -> Option<String> {
+    if !path.symlink_metadata().ok()?.is_file() {
+        return None;
+    }
+
+    let content = fs::read(path).ok()?;
+    if is_binary(&content) {
+        return None;
+    }
+
+    String::from_utf8(content).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::testing::TestFolder;
+
+    use super::{diff_files, preview_clone, ClonePreview};
+
+    #[test]
+    fn should_preview_created_overwritten_and_unchanged_files() {
+        let folder = TestFolder::new()
+            .add_file("rendered/src/main.rs", "fn main() {}\n")
+            .add_file("rendered/README.md", "# app\nnew line\n")
+            .add_file("rendered/LICENSE", "MIT\n")
+            .add_file("destination/README.md", "# app\n")
+            .add_file("destination/LICENSE", "MIT\n");
+        let rendered = folder.join("rendered");
+        let destination = folder.join("destination");
+
+        let preview = preview_clone(&rendered, &destination).unwrap();
+        let diff = diff_files(&rendered, &destination, &preview.overwritten).unwrap();
+
+        assert_eq!(
+            preview,
+            ClonePreview {
+                created: vec!["src/main.rs".to_string()],
+                overwritten: vec!["README.md".to_string()],
+                unchanged: vec!["LICENSE".to_string()],
+            }
+        );
+        assert_eq!(
+            diff,
+            "--- a/README.md\n+++ b/README.md\n@@ -1 +1,2 @@\n # app\n+new line\n"
+        );
+        assert!(!destination.join("src").exists());
+    }
+}
@@ -0,0 +1,361 @@
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Component, Path},
+    process::Command,
+};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+use super::{
+    archive::{export, import, ImportOptions},
+    git::run_git,
+    manifest::SnapshotManifest,
+    store::SnapshotDir,
+    Config,
+};
+
+const INDEX_FILE: &str = "index.json";
+
+/// Shared catalog of snapshots, a folder or git repository holding an `index.json` and
+/// the archives it lists, or an HTTP server serving them
+#[derive(PartialEq, Deserialize, Debug, Clone)]
+pub struct Registry {
+    pub name: String,
+    pub url: String,
+    /// Guessed from `url` when not set
+    pub kind: Option<RegistryKind>,
+}
+
+#[derive(PartialEq, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryKind {
+    Dir,
+    Git,
+    Http,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Debug, Default)]
+pub struct Index {
+    #[serde(default)]
+    pub snapshots: Vec<IndexEntry>,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Debug, Clone)]
+pub struct IndexEntry {
+    pub id: String,
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Archive made by `snapshot export`, relative to the registry
+    pub archive: String,
+}
+
+impl IndexEntry {
+    /// Whether `term` is part of the id, description or a tag, ignoring case
+    pub fn matches(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+
+        self.id.to_lowercase().contains(&term)
+            || self
+                .description
+                .as_ref()
+                .is_some_and(|description| description.to_lowercase().contains(&term))
+            || self
+                .tags
+                .iter()
+                .any(|tag| tag.to_lowercase().contains(&term))
+    }
+}
+
+impl Display for IndexEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}@{}  {}",
+            self.id,
+            self.version,
+            self.description.as_deref().unwrap_or("-")
+        )?;
+
+        if !self.tags.is_empty() {
+            write!(f, "  [{}]", self.tags.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Files of a registry, read in place for folders or cloned for repositories
+enum Location {
+    Local(SnapshotDir),
+    Http(String),
+}
+
+impl Registry {
+    pub fn kind(&self) -> RegistryKind {
+        let is_git = self.url.ends_with(".git")
+            || ["file://", "ssh://", "git://", "git@"]
+                .iter()
+                .any(|scheme| self.url.starts_with(scheme));
+
+        match self.kind {
+            Some(kind) => kind,
+            None if is_git => RegistryKind::Git,
+            None if self.url.starts_with("http://") || self.url.starts_with("https://") => {
+                RegistryKind::Http
+            }
+            None => RegistryKind::Dir,
+        }
+    }
+
+    /// Every snapshot the registry lists
+    pub fn index(&self) -> Result<Index> {
+        read_index(&self.locate()?, self)
+    }
+
+    /// Imports the snapshot `id` of the registry
+    pub fn pull(&self, config: &Config, id: &str, options: &ImportOptions) -> Result<String> {
+        let location = self.locate()?;
+        let index = read_index(&location, self)?;
+
+        let entry = index
+            .snapshots
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| self.error(&format!("no snapshot {id}")))?;
+
+        // Read from the registry, never from elsewhere on the disk or server
+        let archive = Path::new(&entry.archive);
+        if entry.archive.is_empty()
+            || !archive
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(self.error(&format!("invalid archive path {}", entry.archive)));
+        }
+
+        match location {
+            Location::Local(dir) => import(config, &dir.path().join(&entry.archive), options),
+            Location::Http(url) => {
+                let archive = SnapshotDir::temporary();
+                fs::create_dir_all(archive.path())?;
+                let path = archive.path().join(format!("{id}.tar.gz"));
+
+                download(&format!("{url}/{}", entry.archive), &path)
+                    .map_err(|reason| self.error(&reason))?;
+                import(config, &path, options)
+            }
+        }
+    }
+
+    /// Adds the latest version of the snapshot `id` to the registry, replacing the one
+    /// it had. HTTP registries are read-only.
+    pub fn publish(&self, config: &Config, id: &str) -> Result<IndexEntry> {
+        let location = self.locate()?;
+        let Location::Local(dir) = &location else {
+            return Err(self.error("HTTP registries are read-only"));
+        };
+
+        let archive = format!("snapshots/{id}.tar.gz");
+        fs::create_dir_all(dir.path().join("snapshots"))?;
+        export(config, id, &dir.path().join(&archive))?;
+
+        let version = config.versions(id)?.last().copied().unwrap_or_default();
+        let manifest = SnapshotManifest::load(config, id, version)?.unwrap_or_default();
+        let entry = IndexEntry {
+            id: id.to_string(),
+            version,
+            description: manifest.description,
+            tags: manifest.tags,
+            archive,
+        };
+
+        let mut index = read_index(&location, self)?;
+        index.snapshots.retain(|published| published.id != id);
+        index.snapshots.push(entry.clone());
+        index.snapshots.sort_by(|a, b| a.id.cmp(&b.id));
+        fs::write(
+            dir.path().join(INDEX_FILE),
+            serde_json::to_string_pretty(&index)?,
+        )?;
+
+        if self.kind() == RegistryKind::Git {
+            let path = dir.path().to_string_lossy().to_string();
+            let message = format!("Publish {id}@{version}");
+
+            run_git(&["-C", &path, "add", "-A"])
+                .and_then(|_| run_git(&["-C", &path, "commit", "--quiet", "-m", &message]))
+                .and_then(|_| run_git(&["-C", &path, "push", "--quiet", "origin", "HEAD"]))
+                .map_err(|e| self.error(&e.to_string()))?;
+        }
+
+        Ok(entry)
+    }
+
+    fn locate(&self) -> Result<Location> {
+        match self.kind() {
+            RegistryKind::Dir => Ok(Location::Local(SnapshotDir::existing(&self.url))),
+            RegistryKind::Git => {
+                let dir = SnapshotDir::temporary();
+                let path = dir.path().to_string_lossy().to_string();
+
+                run_git(&["clone", "--quiet", "--", &self.url, &path])
+                    .map_err(|e| self.error(&e.to_string()))?;
+                Ok(Location::Local(dir))
+            }
+            RegistryKind::Http => Ok(Location::Http(self.url.trim_end_matches('/').to_string())),
+        }
+    }
+
+    fn error(&self, reason: &str) -> Error {
+        Error::RegistryError {
+            name: self.name.clone(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Index of a registry, empty when nothing was published to it yet
+fn read_index(location: &Location, registry: &Registry) -> Result<Index> {
+    let content = match location {
+        Location::Local(dir) => {
+            let path = dir.path().join(INDEX_FILE);
+            if !path.exists() {
+                return Ok(Index::default());
+            }
+            fs::read_to_string(path)?
+        }
+        Location::Http(url) => {
+            let index = SnapshotDir::temporary();
+            fs::create_dir_all(index.path())?;
+            let path = index.path().join(INDEX_FILE);
+
+            download(&format!("{url}/{INDEX_FILE}"), &path)
+                .map_err(|reason| registry.error(&reason))?;
+            fs::read_to_string(path)?
+        }
+    };
+
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Fetches `url` with curl, the reason of the failure otherwise
+fn download(url: &str, dst: &Path) -> std::result::Result<(), String> {
+    let output = Command::new("curl")
+        .args([
+            "--fail",
+            "--silent",
+            "--show-error",
+            "--location",
+            "--output",
+        ])
+        .arg(dst)
+        .arg(url)
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                "curl is needed to read HTTP registries and was not found".to_string()
+            }
+            _ => format!("failed to run curl: {e}"),
+        })?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::core::{
+        archive::ImportOptions,
+        store::write_version,
+        testing::{serve, test_config, TestFolder},
+    };
+
+    use super::{IndexEntry, Registry, RegistryKind};
+
+    #[test]
+    fn should_guess_registry_kind_from_url() {
+        let kind = |url: &str| {
+            Registry {
+                name: "org".to_string(),
+                url: url.to_string(),
+                kind: None,
+            }
+            .kind()
+        };
+
+        assert_eq!(kind("/srv/templates"), RegistryKind::Dir);
+        assert_eq!(kind("file:///srv/templates.git"), RegistryKind::Git);
+        assert_eq!(
+            kind("https://github.com/org/templates.git"),
+            RegistryKind::Git
+        );
+        assert_eq!(kind("https://templates.example.com"), RegistryKind::Http);
+    }
+
+    #[test]
+    fn should_match_search_term_in_id_description_or_tags() {
+        let entry = IndexEntry {
+            id: "web_service".to_string(),
+            version: 2,
+            description: Some("Axum service".to_string()),
+            tags: vec!["Rust".to_string()],
+            archive: "snapshots/web_service.tar.gz".to_string(),
+        };
+
+        assert!(entry.matches("web"));
+        assert!(entry.matches("axum"));
+        assert!(entry.matches("rust"));
+        assert!(!entry.matches("python"));
+        assert_eq!(entry.to_string(), "web_service@2  Axum service  [Rust]");
+    }
+
+    #[test]
+    fn should_pull_from_http_registries_and_refuse_archives_outside_them() {
+        let folder = TestFolder::new().add_file("project/setuprs.toml", "[project]\nname = 'web'");
+        let (mine, theirs) = (
+            test_config(folder.join("mine")),
+            test_config(folder.join("theirs")),
+        );
+        let registry = |url: String| Registry {
+            name: "org".to_string(),
+            url,
+            kind: None,
+        };
+
+        write_version(&mine, "web", 1, &folder.join("project")).unwrap();
+
+        let root = folder.join("registry");
+        let local = registry(root.display().to_string());
+        local.publish(&mine, "web").unwrap();
+
+        let http = registry(serve(fs::canonicalize(&root).unwrap()));
+        let listed = http.index().unwrap().snapshots;
+        let pulled = http.pull(&theirs, "web", &ImportOptions::default());
+        let read_only = http.publish(&mine, "web");
+        let versions = theirs.versions("web").unwrap();
+
+        let index = root.join("index.json");
+        let content = fs::read_to_string(&index).unwrap();
+        fs::write(&index, content.replace("snapshots/web", "../web")).unwrap();
+        let outside = local.pull(&theirs, "web", &ImportOptions::default());
+
+        assert_eq!(listed.len(), 1);
+        assert_eq!(pulled.unwrap(), "web");
+        assert!(read_only.is_err());
+        assert_eq!(versions, vec![1]);
+        assert!(outside
+            .unwrap_err()
+            .to_string()
+            .contains("invalid archive path ../web.tar.gz"));
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    fs,
+    path::{Path, MAIN_SEPARATOR},
+};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+use super::{git::run_git, store::SnapshotDir, Config};
+
+/// Where a clone takes its files from
+#[derive(Debug, PartialEq)]
+pub enum Source {
+    /// A stored snapshot, as `id` or `id@version`
+    Snapshot(String),
+    /// A project folder, used as it is
+    Dir(String),
+    /// A git repository, cloned to a temporary folder
+    Git(String),
+}
+
+/// Kind of a `Source`, recorded in the clones to open it again on update
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    Snapshot,
+    Dir,
+    Git,
+}
+
+/// Files of an opened source and what to record to open it again
+pub struct Opened {
+    pub dir: SnapshotDir,
+    /// Snapshot id, the reference itself for other sources
+    pub id: String,
+    /// Only snapshots have versions
+    pub version: Option<u32>,
+    /// Commit checked out from a git repository
+    pub commit: Option<String>,
+}
+
+impl Source {
+    /// URLs are git repositories, like paths to a bare repository. Other paths, starting
+    /// with `.` or containing a separator, are project folders and the rest snapshot ids.
+    pub fn parse(reference: &str) -> Self {
+        let is_url = ["file://", "http://", "https://", "ssh://", "git://", "git@"]
+            .iter()
+            .any(|scheme| reference.starts_with(scheme));
+        let is_path = reference.starts_with('.') || reference.contains(['/', MAIN_SEPARATOR]);
+
+        match (is_url, is_path) {
+            (true, _) => Self::Git(reference.to_string()),
+            (false, true) if is_bare_repository(Path::new(reference)) => {
+                Self::Git(reference.to_string())
+            }
+            (false, true) => Self::Dir(reference.to_string()),
+            (false, false) => Self::Snapshot(reference.to_string()),
+        }
+    }
+
+    /// The source a clone recorded as `kind` and `reference`
+    pub fn recorded(kind: SourceKind, reference: &str) -> Self {
+        let reference = reference.to_string();
+
+        match kind {
+            SourceKind::Snapshot => Self::Snapshot(reference),
+            SourceKind::Dir => Self::Dir(reference),
+            SourceKind::Git => Self::Git(reference),
+        }
+    }
+
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            Self::Snapshot(_) => SourceKind::Snapshot,
+            Self::Dir(_) => SourceKind::Dir,
+            Self::Git(_) => SourceKind::Git,
+        }
+    }
+
+    /// Files of the source, `git_ref` being the branch, tag or commit of a repository
+    /// (its default branch when not given)
+    pub fn open(
+        &self,
+        config: &Config,
+        git_ref: Option<&str>,
+        preserve_mtime: bool,
+    ) -> Result<Opened> {
+        let opened = |dir, id: &str, version, commit| Opened {
+            dir,
+            id: id.to_string(),
+            version,
+            commit,
+        };
+
+        match (self, git_ref) {
+            (Self::Git(url), _) => {
+                let (dir, commit) = fetch(url, git_ref)?;
+                Ok(opened(dir, url, None, Some(commit)))
+            }
+            (Self::Snapshot(reference) | Self::Dir(reference), Some(_)) => {
+                Err(invalid(reference, "--ref only applies to git repositories"))
+            }
+            (Self::Dir(path), None) => match Path::new(path).is_dir() {
+                true => Ok(opened(SnapshotDir::existing(path), path, None, None)),
+                false => Err(invalid(path, "no such folder")),
+            },
+            (Self::Snapshot(reference), None) => {
+                let (id, version) = config.resolve_snapshot(reference)?;
+                let dir = SnapshotDir::open(config, &id, version, preserve_mtime)?;
+
+                Ok(opened(dir, &id, version, None))
+            }
+        }
+    }
+}
+
+fn is_bare_repository(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir()
+}
+
+/// Clones `url` with the git binary, without its history in the result, and the commit
+/// checked out
+fn fetch(url: &str, git_ref: Option<&str>) -> Result<(SnapshotDir, String)> {
+    let dir = SnapshotDir::temporary();
+    let path = dir.path().to_string_lossy().to_string();
+
+    let commit = run_git(&["clone", "--quiet", "--", url, &path])
+        .and_then(|_| match git_ref {
+            Some(git_ref) => run_git(&["-C", &path, "checkout", "--quiet", git_ref]),
+            None => Ok(String::new()),
+        })
+        .and_then(|_| run_git(&["-C", &path, "rev-parse", "HEAD"]))
+        .map_err(|e| invalid(url, &e.to_string()))?;
+    fs::remove_dir_all(dir.path().join(".git"))?;
+
+    Ok((dir, commit))
+}
+
+fn invalid(reference: &str, reason: &str) -> Error {
+    Error::InvalidSource {
+        reference: reference.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Source;
+
+    #[test]
+    fn should_tell_snapshots_folders_and_repositories_apart() {
+        let cases = [
+            ("web", Source::Snapshot("web".to_string())),
+            ("web@2", Source::Snapshot("web@2".to_string())),
+            (".", Source::Dir(".".to_string())),
+            ("templates/web", Source::Dir("templates/web".to_string())),
+            (
+                "file:///srv/web.git",
+                Source::Git("file:///srv/web.git".to_string()),
+            ),
+            (
+                "git@github.com:org/web.git",
+                Source::Git("git@github.com:org/web.git".to_string()),
+            ),
+        ];
+
+        for (reference, expected) in cases {
+            assert_eq!(Source::parse(reference), expected);
+        }
+    }
+}
@@ -0,0 +1,741 @@
+use std::{
+    collections::BTreeSet,
+    env,
+    fmt::Display,
+    fs::{self, File, FileTimes, Metadata},
+    io,
+    path::{Component, Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+
+use super::{
+    manifest::SnapshotManifest,
+    utils::{create_symlink, points_inside, walk},
+    validate_snapshot_id, Config,
+};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// One file, folder or symlink of a snapshot version, files pointing to the blob
+/// holding their content
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TreeEntry {
+    /// Relative to the snapshot, `/` separated
+    pub path: String,
+    pub kind: EntryKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Nanoseconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+}
+
+/// Every entry of a snapshot version, parents before their children
+#[derive(Serialize, Deserialize, Debug, PartialEq, Default)]
+pub struct Tree {
+    #[serde(default, rename = "entry")]
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    pub fn load(config: &Config, id: &str, version: u32) -> Result<Self> {
+        let path = config.tree_path(id, version);
+
+        if !path.exists() {
+            return Err(Error::SnapshotVersionDontExist(format!("{id}@{version}")));
+        }
+
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Refuses entries a checkout would write outside its folder: paths that aren't plain
+    /// relative ones or that go through a symlink of the tree. Symlinks pointing outside
+    /// of it are refused too unless `allow_outside_symlinks`, the reason otherwise.
+    pub fn check(&self, allow_outside_symlinks: bool) -> std::result::Result<(), String> {
+        let symlinks = self
+            .entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::Symlink)
+            .map(|entry| Path::new(&entry.path))
+            .collect::<Vec<_>>();
+
+        for entry in &self.entries {
+            let path = Path::new(&entry.path);
+
+            if entry.path.is_empty()
+                || !path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+            {
+                return Err(format!("invalid path {}", entry.path));
+            }
+
+            if let Some(link) = symlinks
+                .iter()
+                .find(|link| path != **link && path.starts_with(link))
+            {
+                return Err(format!(
+                    "{} goes through the symlink {}",
+                    entry.path,
+                    link.display()
+                ));
+            }
+
+            let target = Path::new(entry.target.as_deref().unwrap_or_default());
+            if entry.kind == EntryKind::Symlink
+                && !allow_outside_symlinks
+                && !points_inside(Path::new(""), path, target)
+            {
+                return Err(format!(
+                    "symlink {} points outside the snapshot to {}",
+                    entry.path,
+                    target.display()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Entries of `dir` as they would be stored, without storing anything
+    pub fn scan(dir: &Path) -> Result<Self> {
+        let mut tree = Tree::default();
+        add_entries(dir, &mut tree.entries)?;
+
+        Ok(tree)
+    }
+
+    /// Stores the tree as `version` of the snapshot `id`, with the blobs of its files
+    /// read from `dir`
+    pub fn write(&self, config: &Config, id: &str, version: u32, dir: &Path) -> Result<()> {
+        for entry in &self.entries {
+            if let Some(blob) = &entry.blob {
+                add_blob(config, blob, &dir.join(&entry.path))?;
+            }
+        }
+
+        let path = config.tree_path(id, version);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Written last, blobs are never referenced before they are complete
+        fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Whether both trees hold the same paths, contents, targets and modes, whatever
+    /// their modification times
+    pub fn same_content(&self, other: &Tree) -> bool {
+        self.entries.len() == other.entries.len()
+            && self.entries.iter().zip(&other.entries).all(|(a, b)| {
+                a.path == b.path
+                    && a.kind == b.kind
+                    && a.blob == b.blob
+                    && a.target == b.target
+                    && a.mode == b.mode
+            })
+    }
+}
+
+/// Stores the content of `dir` as `version` of the snapshot `id`. Files already stored
+/// by any snapshot or version are not written again.
+pub fn write_version(config: &Config, id: &str, version: u32, dir: &Path) -> Result<()> {
+    Tree::scan(dir)?.write(config, id, version, dir)
+}
+
+fn add_entries(root: &Path, entries: &mut Vec<TreeEntry>) -> Result<()> {
+    for (relative, metadata) in walk(root)? {
+        let path = root.join(&relative);
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut entry = TreeEntry {
+            path: relative,
+            kind: EntryKind::File,
+            blob: None,
+            target: None,
+            mode: file_mode(&metadata),
+            mtime: metadata
+                .modified()
+                .ok()
+                .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                .and_then(|mtime| u64::try_from(mtime.as_nanos()).ok()),
+        };
+
+        if metadata.is_symlink() {
+            entry.kind = EntryKind::Symlink;
+            entry.target = Some(fs::read_link(&path)?.to_string_lossy().to_string());
+            entry.mode = None;
+            entry.mtime = None;
+        } else if metadata.is_dir() {
+            entry.kind = EntryKind::Dir;
+        } else {
+            entry.blob = Some(hash_file(&path)?);
+        }
+
+        entries.push(entry);
+    }
+
+    Ok(())
+}
+
+/// Stores `path` as the blob `hash` unless it is already there, the caller vouching for
+/// the hash
+pub fn add_blob(config: &Config, hash: &str, path: &Path) -> Result<()> {
+    let blob_path = config.blob_path(hash);
+    if blob_path.exists() {
+        return Ok(());
+    }
+
+    let objects = config.objects_path();
+    fs::create_dir_all(&objects)?;
+    if let Some(parent) = blob_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // Copied aside and renamed so an interrupted copy never looks like a stored blob
+    let temporary = objects.join(format!("tmp-{}", Uuid::new_v4()));
+    let stored = fs::copy(path, &temporary).and_then(|_| fs::rename(&temporary, &blob_path));
+    if let Err(e) = stored {
+        let _ = fs::remove_file(&temporary);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Recreates `version` of the snapshot `id` in `dst`
+pub fn checkout(
+    config: &Config,
+    id: &str,
+    version: u32,
+    dst: &Path,
+    preserve_mtime: bool,
+) -> Result<()> {
+    let tree = Tree::load(config, id, version)?;
+    // Snapshots created with `--outside-symlinks keep` may point outside on purpose,
+    // links are recreated and never written through
+    tree.check(true)
+        .map_err(|reason| Error::CorruptedSnapshot {
+            snapshot: format!("{id}@{version}"),
+            reason,
+        })?;
+    fs::create_dir_all(dst)?;
+
+    for entry in &tree.entries {
+        let path = dst.join(&entry.path);
+
+        match entry.kind {
+            EntryKind::Dir => fs::create_dir_all(&path)?,
+            EntryKind::Symlink => create_symlink(
+                Path::new(entry.target.as_deref().unwrap_or_default()),
+                &path,
+            )?,
+            EntryKind::File => {
+                let blob = entry.blob.as_deref().unwrap_or_default();
+                fs::copy(config.blob_path(blob), &path)?;
+                apply_metadata(&path, entry, preserve_mtime)?;
+            }
+        }
+    }
+
+    // Folders last, files written into them would change their mtime or be refused by
+    // a read-only mode
+    for entry in tree.entries.iter().rev() {
+        if entry.kind == EntryKind::Dir {
+            apply_metadata(&dst.join(&entry.path), entry, preserve_mtime)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn apply_metadata(path: &Path, entry: &TreeEntry, preserve_mtime: bool) -> Result<()> {
+    if let (true, Some(mtime)) = (preserve_mtime, entry.mtime) {
+        let mtime = UNIX_EPOCH + Duration::from_nanos(mtime);
+        File::open(path)?.set_times(FileTimes::new().set_modified(mtime))?;
+    }
+
+    if let Some(mode) = entry.mode {
+        set_mode(path, mode)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Files to clone from, a snapshot version checked out in a temporary folder removed on
+/// drop, or a folder used as it is like snapshots created before versions were stored
+pub struct SnapshotDir {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl SnapshotDir {
+    /// Checks out `version` of the snapshot `id`, `None` being a snapshot without versions
+    pub fn open(
+        config: &Config,
+        id: &str,
+        version: Option<u32>,
+        preserve_mtime: bool,
+    ) -> Result<Self> {
+        let Some(version) = version else {
+            return Ok(Self::existing(config.snapshot_path(id)));
+        };
+
+        let dir = Self::temporary();
+        checkout(config, id, version, &dir.path, preserve_mtime)?;
+
+        Ok(dir)
+    }
+
+    /// A folder used as it is, left in place on drop
+    pub fn existing(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            temporary: false,
+        }
+    }
+
+    /// A not yet created folder in the temporary directory, removed on drop
+    pub fn temporary() -> Self {
+        Self {
+            path: env::temp_dir().join(format!("setuprs-checkout-{}", Uuid::new_v4())),
+            temporary: true,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for SnapshotDir {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// Removes every version of the snapshot `id` with its metadata. Its files stay stored
+/// until `gc`, other snapshots may use them.
+pub fn delete(config: &Config, id: &str) -> Result<()> {
+    if !config.snapshot_exists(id)? {
+        return Err(Error::SnapshotDontExist);
+    }
+
+    for path in [
+        config.trees_path().join(id),
+        config.manifests_path(id),
+        config.snapshot_path(id),
+    ] {
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves every version of the snapshot `id` with its metadata to `new_id`
+pub fn rename(config: &Config, id: &str, new_id: &str) -> Result<()> {
+    check_new_id(config, id, new_id)?;
+
+    for (from, to) in [
+        (
+            config.trees_path().join(id),
+            config.trees_path().join(new_id),
+        ),
+        (config.manifests_path(id), config.manifests_path(new_id)),
+        (config.snapshot_path(id), config.snapshot_path(new_id)),
+    ] {
+        if from.exists() {
+            fs::rename(from, to)?;
+        }
+    }
+
+    for version in config.versions(new_id)? {
+        if let Some(manifest) = SnapshotManifest::load(config, new_id, version)? {
+            SnapshotManifest {
+                id: new_id.to_string(),
+                ..manifest
+            }
+            .write(config)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies every version of the snapshot `id` with its metadata to `new_id`, only the
+/// trees are copied since both share the same stored files
+pub fn duplicate(config: &Config, id: &str, new_id: &str) -> Result<()> {
+    check_new_id(config, id, new_id)?;
+
+    let versions = config.versions(id)?;
+
+    // Snapshots created before versions were stored become the first version of the copy
+    if versions.is_empty() {
+        let path = config.snapshot_path(id);
+        write_version(config, new_id, 1, &path)?;
+        return SnapshotManifest::scan(new_id, 1, &path)?.write(config);
+    }
+
+    for version in versions {
+        let tree_path = config.tree_path(new_id, version);
+        if let Some(parent) = tree_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(config.tree_path(id, version), tree_path)?;
+
+        if let Some(manifest) = SnapshotManifest::load(config, id, version)? {
+            SnapshotManifest {
+                id: new_id.to_string(),
+                ..manifest
+            }
+            .write(config)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_new_id(config: &Config, id: &str, new_id: &str) -> Result<()> {
+    validate_snapshot_id(new_id)?;
+
+    if !config.snapshot_exists(id)? {
+        return Err(Error::SnapshotDontExist);
+    }
+
+    if config.snapshot_exists(new_id)? {
+        return Err(Error::SnapshotAlreadyExists(new_id.to_string()));
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GcReport {
+    pub removed: u64,
+    pub freed: u64,
+}
+
+impl Display for GcReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Removed {} unreferenced objects, {} bytes freed",
+            self.removed, self.freed
+        )
+    }
+}
+
+/// How old a temporary copy in the objects folder must be before `gc` takes it for
+/// the leftover of an interrupted write rather than one `add_blob` is still making
+const TEMPORARY_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Removes the blobs no version of any snapshot refers to anymore, along with copies
+/// left behind by interrupted writes over an hour ago
+pub fn gc(config: &Config) -> Result<GcReport> {
+    let mut referenced = BTreeSet::new();
+    let trees = config.trees_path();
+
+    if trees.exists() {
+        for snapshot in fs::read_dir(&trees)? {
+            let snapshot = snapshot?.path();
+
+            for version in fs::read_dir(&snapshot)? {
+                let tree: Tree = toml::from_str(&fs::read_to_string(version?.path())?)?;
+                referenced.extend(tree.entries.into_iter().filter_map(|entry| entry.blob));
+            }
+        }
+    }
+
+    let mut report = GcReport::default();
+    let objects = config.objects_path();
+
+    if !objects.exists() {
+        return Ok(report);
+    }
+
+    for entry in fs::read_dir(&objects)? {
+        let entry = entry?;
+        let prefix = entry.file_name().to_string_lossy().to_string();
+
+        if !entry.file_type()?.is_dir() {
+            let metadata = entry.metadata()?;
+            // A modification time in the future counts as a copy being written
+            let interrupted = metadata
+                .modified()?
+                .elapsed()
+                .is_ok_and(|age| age >= TEMPORARY_GRACE_PERIOD);
+            if !interrupted {
+                continue;
+            }
+
+            report.freed += metadata.len();
+            report.removed += 1;
+            fs::remove_file(entry.path())?;
+            continue;
+        }
+
+        for blob in fs::read_dir(entry.path())? {
+            let blob = blob?;
+            let hash = format!("{prefix}{}", blob.file_name().to_string_lossy());
+
+            if !referenced.contains(&hash) {
+                report.freed += blob.metadata()?.len();
+                report.removed += 1;
+                fs::remove_file(blob.path())?;
+            }
+        }
+
+        if fs::read_dir(entry.path())?.next().is_none() {
+            fs::remove_dir(entry.path())?;
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        time::{Duration, SystemTime},
+    };
+
+    use crate::core::{
+        testing::{test_config, TestFolder},
+        utils::hash_dir,
+        Config,
+    };
+
+    use super::{
+        checkout, delete, duplicate, gc, write_version, EntryKind, GcReport, Tree, TreeEntry,
+    };
+
+    fn blob_modified(config: &Config, hash: &str) -> SystemTime {
+        fs::metadata(config.blob_path(hash))
+            .unwrap()
+            .modified()
+            .unwrap()
+    }
+
+    #[test]
+    fn should_store_identical_files_once_and_checkout_every_version() {
+        let folder = TestFolder::new()
+            .add_folder("project/src/empty")
+            .add_file("project/setuprs.toml", "[project]\nname='a'")
+            .add_file("project/src/main.rs", "fn main() {}")
+            .add_file("project/src/copy.rs", "fn main() {}");
+        let config = test_config(folder.path());
+        let project = folder.join("project");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::write(project.join("run.sh"), "#!/bin/sh").unwrap();
+            fs::set_permissions(project.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+            std::os::unix::fs::symlink("src/main.rs", project.join("main")).unwrap();
+        }
+
+        write_version(&config, "a", 1, &project).unwrap();
+        let first_hash = hash_dir(&project).unwrap();
+        let main_blob = Tree::load(&config, "a", 1)
+            .unwrap()
+            .entries
+            .into_iter()
+            .find(|entry| entry.path == "src/main.rs")
+            .and_then(|entry| entry.blob)
+            .unwrap();
+        let stored_at = blob_modified(&config, &main_blob);
+
+        fs::write(project.join("setuprs.toml"), "[project]\nname='b'").unwrap();
+        write_version(&config, "a", 2, &project).unwrap();
+        write_version(&config, "b", 1, &project).unwrap();
+
+        let blobs = fs::read_dir(config.objects_path())
+            .unwrap()
+            .map(|prefix| fs::read_dir(prefix.unwrap().path()).unwrap().count())
+            .sum::<usize>();
+        // main.rs and copy.rs share a blob, run.sh and both setuprs.toml have their own
+        assert_eq!(blobs, if cfg!(unix) { 4 } else { 3 });
+
+        let checkout_path = folder.join("checkout");
+        checkout(&config, "a", 1, &checkout_path, false).unwrap();
+        assert_eq!(hash_dir(&checkout_path).unwrap(), first_hash);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(checkout_path.join("run.sh"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        assert!(checkout(&config, "a", 3, &checkout_path, false).is_err());
+        assert_eq!(blob_modified(&config, &main_blob), stored_at);
+    }
+
+    #[test]
+    fn duplicate_should_store_snapshots_created_before_versions() {
+        let folder = TestFolder::new().add_file("snapshots/legacy/main.rs", "legacy");
+        let config = test_config(folder.path());
+
+        duplicate(&config, "legacy", "copy").unwrap();
+        assert_eq!(config.versions("copy").unwrap(), vec![1]);
+
+        delete(&config, "legacy").unwrap();
+        assert!(!config.snapshot_path("legacy").exists());
+        assert!(config.snapshot_exists("copy").unwrap());
+    }
+
+    #[test]
+    fn gc_should_only_remove_unreferenced_blobs() {
+        let folder = TestFolder::new()
+            .add_file("project/kept.txt", "kept")
+            .add_file("project/removed.txt", "removed!");
+        let config = test_config(folder.path());
+        let project = folder.join("project");
+
+        write_version(&config, "a", 1, &project).unwrap();
+        fs::remove_file(project.join("removed.txt")).unwrap();
+        write_version(&config, "b", 1, &project).unwrap();
+
+        assert_eq!(gc(&config).unwrap(), GcReport::default());
+
+        fs::remove_dir_all(config.tree_path("a", 1).parent().unwrap()).unwrap();
+        let interrupted = config.objects_path().join("tmp-interrupted");
+        fs::write(&interrupted, "in").unwrap();
+        File::options()
+            .write(true)
+            .open(&interrupted)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))
+            .unwrap();
+        fs::write(config.objects_path().join("tmp-writing"), "being written").unwrap();
+        assert_eq!(
+            gc(&config).unwrap(),
+            GcReport {
+                removed: 2,
+                freed: 10
+            }
+        );
+        assert!(!interrupted.exists());
+        assert!(config.objects_path().join("tmp-writing").exists());
+
+        let checkout_path = folder.join("checkout");
+        checkout(&config, "b", 1, &checkout_path, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(checkout_path.join("kept.txt")).unwrap(),
+            "kept"
+        );
+    }
+
+    #[test]
+    fn should_refuse_entries_written_outside_the_checkout() {
+        let entry = |path: &str, kind: EntryKind, target: Option<&str>| TreeEntry {
+            path: path.to_string(),
+            kind,
+            blob: None,
+            target: target.map(String::from),
+            mode: None,
+            mtime: None,
+        };
+        let check = |entries: Vec<TreeEntry>, allow_outside_symlinks: bool| {
+            Tree { entries }.check(allow_outside_symlinks)
+        };
+
+        assert!(check(vec![entry("src", EntryKind::Dir, None)], false).is_ok());
+        assert!(check(vec![entry("../../tmp/evil", EntryKind::File, None)], false).is_err());
+        assert!(check(vec![entry("/tmp/evil", EntryKind::File, None)], false).is_err());
+        assert!(check(vec![entry("", EntryKind::File, None)], false).is_err());
+        assert!(check(
+            vec![
+                entry("link", EntryKind::Symlink, Some("src")),
+                entry("link/evil", EntryKind::File, None),
+            ],
+            true
+        )
+        .is_err());
+        assert!(check(
+            vec![entry("link", EntryKind::Symlink, Some("../outside"))],
+            false
+        )
+        .is_err());
+        assert!(check(
+            vec![entry("link", EntryKind::Symlink, Some("/etc/passwd"))],
+            true
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn should_not_checkout_a_corrupted_tree() {
+        let folder = TestFolder::new().add_file(
+            "snapshots/.trees/evil/1.toml",
+            "[[entry]]\npath = '../escaped'\nkind = 'dir'\n",
+        );
+        let config = test_config(folder.path());
+
+        let checked_out = checkout(&config, "evil", 1, &folder.join("out"), false);
+
+        assert!(matches!(
+            checked_out,
+            Err(crate::error::Error::CorruptedSnapshot { .. })
+        ));
+        assert!(!folder.join("escaped").exists());
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use heck::{ToKebabCase, ToLowerCamelCase, ToSnakeCase, ToUpperCamelCase};
+use minijinja::{AutoEscape, Environment, UndefinedBehavior, Value};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+pub struct Renderer {
+    env: Environment<'static>,
+    context: Value,
+}
+
+impl Renderer {
+    pub fn new<S: Serialize>(answers: &S) -> Self {
+        let mut env = Environment::new();
+
+        // Keep rendered files shaped like the originals: no lost trailing newline
+        // and no blank lines left behind by block tags
+        env.set_keep_trailing_newline(true);
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_undefined_behavior(UndefinedBehavior::SemiStrict);
+        // Snapshots hold source code, never escape based on the file extension
+        env.set_auto_escape_callback(|_| AutoEscape::None);
+
+        // Case conversions on top of the builtin filters (upper, lower, replace, trim, default...)
+        env.add_filter("snake_case", |s: String| s.to_snake_case());
+        env.add_filter("kebab_case", |s: String| s.to_kebab_case());
+        env.add_filter("pascal_case", |s: String| s.to_upper_camel_case());
+        env.add_filter("camel_case", |s: String| s.to_lower_camel_case());
+
+        Self {
+            env,
+            context: Value::from_serialize(answers),
+        }
+    }
+
+    pub fn render(&self, file: &Path, content: &str) -> Result<String> {
+        let name = file.display().to_string();
+
+        self.env
+            .render_named_str(&name, content, &self.context)
+            .map_err(|e| Error::TemplateError {
+                file: name,
+                line: e.line().unwrap_or(0),
+                reason: match e.detail() {
+                    Some(detail) => format!("{}: {detail}", e.kind()),
+                    None => e.kind().to_string(),
+                },
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use crate::error::Error;
+
+    use super::Renderer;
+
+    fn render(answers: &[(&str, &str)], content: &str) -> String {
+        let answers: HashMap<&str, &str> = answers.iter().cloned().collect();
+        Renderer::new(&answers)
+            .render(Path::new("file.txt"), content)
+            .unwrap()
+    }
+
+    #[test]
+    fn should_replace_variables() {
+        assert_eq!(
+            render(&[("name", "setuprs")], "project = {{name}}\n"),
+            "project = setuprs\n"
+        );
+    }
+
+    #[test]
+    fn should_render_conditional_blocks() {
+        let content = "start\n{% if use_docker == 'yes' %}\ndocker\n{% endif %}\nend\n";
+
+        assert_eq!(
+            render(&[("use_docker", "yes")], content),
+            "start\ndocker\nend\n"
+        );
+        assert_eq!(render(&[("use_docker", "no")], content), "start\nend\n");
+    }
+
+    #[test]
+    fn should_render_loops() {
+        let content = "{% for service in services | split(',') %}\n- {{service}}\n{% endfor %}\n";
+
+        assert_eq!(render(&[("services", "api,db")], content), "- api\n- db\n");
+    }
+
+    #[test]
+    fn should_apply_case_and_string_filters() {
+        let answers = [("name", " My Cool-App ")];
+
+        assert_eq!(render(&answers, "{{name | snake_case}}"), "my_cool_app");
+        assert_eq!(render(&answers, "{{name | kebab_case}}"), "my-cool-app");
+        assert_eq!(render(&answers, "{{name | pascal_case}}"), "MyCoolApp");
+        assert_eq!(render(&answers, "{{name | camel_case}}"), "myCoolApp");
+        assert_eq!(render(&answers, "{{name | trim | upper}}"), "MY COOL-APP");
+        assert_eq!(
+            render(&answers, "{{name | trim | lower | replace(' ', '/')}}"),
+            "my/cool-app"
+        );
+        assert_eq!(render(&answers, "{{missing | default('x')}}"), "x");
+    }
+
+    #[test]
+    fn should_point_to_file_and_line_when_render_fails() {
+        let answers: HashMap<&str, &str> = HashMap::new();
+        let err = Renderer::new(&answers)
+            .render(Path::new("src/main.rs"), "fn main() {\n    {{missing}}\n}")
+            .unwrap_err();
+
+        match err {
+            Error::TemplateError { file, line, .. } => {
+                assert_eq!(file, "src/main.rs");
+                assert_eq!(line, 2);
+            }
+            _ => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn should_point_to_line_of_unclosed_block() {
+        let answers: HashMap<&str, &str> = HashMap::new();
+        let err = Renderer::new(&answers)
+            .render(Path::new("README.md"), "a\nb\n{% if x %}\nc\n")
+            .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .starts_with("Failed to render template README.md:"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Fixtures shared by the unit tests of the core modules and the command line
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use assert_cmd::Command;
+use uuid::Uuid;
+
+use super::{utils::search_file_create_config_folder_if_not_found, Config};
+
+/// Folder named after a new uuid in the working directory, removed with everything in it
+/// when dropped, failed asserts included
+pub struct TestFolder(PathBuf);
+
+impl TestFolder {
+    pub fn new() -> Self {
+        let folder = PathBuf::from(Uuid::new_v4().to_string());
+        fs::create_dir(&folder).unwrap();
+
+        Self(folder)
+    }
+
+    pub fn add_folder(self, name: &str) -> Self {
+        fs::create_dir_all(self.join(name)).unwrap();
+        self
+    }
+
+    /// Writes `content` to `name`, creating the folders it is in
+    pub fn add_file(self, name: &str, content: &str) -> Self {
+        let path = self.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    /// Writes `file.toml`, a config storing its snapshots in the `snapshots` folder of
+    /// this one
+    pub fn with_config(self) -> Self {
+        search_file_create_config_folder_if_not_found(
+            &self.join("file.toml").display().to_string(),
+            &test_config(self.path()),
+        )
+        .unwrap();
+        self
+    }
+
+    /// The setuprs binary run with `args` and the config written by `with_config`
+    pub fn setuprs(&self, args: &[&str]) -> Command {
+        let mut command = Command::cargo_bin("setuprs").unwrap();
+        command
+            .arg("--config")
+            .arg(self.join("file.toml"))
+            .args(args);
+        command
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TestFolder {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Config storing its snapshots in `folder/snapshots`
+pub fn test_config(folder: impl AsRef<Path>) -> Config {
+    Config {
+        config_file_path: ".".to_string(),
+        debug_mode: "error".to_string(),
+        snapshots_path: folder.as_ref().join("snapshots").display().to_string(),
+        registries: vec![],
+    }
+}
+
+/// Serves the files of `root` over HTTP until the tests end, returning its URL
+pub fn serve(root: impl Into<PathBuf>) -> String {
+    let root = root.into();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+
+            let path = request.split_whitespace().nth(1).unwrap_or("/");
+            let response = match fs::read(root.join(path.trim_start_matches('/'))) {
+                Ok(body) => [
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len())
+                        .into_bytes(),
+                    body,
+                ]
+                .concat(),
+                Err(_) => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+            };
+            let _ = stream.write_all(&response);
+        }
+    });
+
+    url
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::error::Result;
+
+use super::{
+    answers::ANSWERS_FILE,
+    utils::{create_symlink, is_binary, walk, write_sections},
+};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct UpdateReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl Display for UpdateReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_sections(
+            f,
+            &[
+                ("Added", &self.added),
+                ("Updated", &self.updated),
+                ("Removed", &self.removed),
+                ("Skipped, changed or removed locally", &self.skipped),
+                ("Conflicts, resolve the markers by hand", &self.conflicts),
+            ],
+        )
+    }
+}
+
+/// A file or symlink of one of the merged trees
+#[derive(Debug, PartialEq)]
+enum Entry {
+    File {
+        content: Vec<u8>,
+        permissions: fs::Permissions,
+    },
+    Symlink(PathBuf),
+}
+
+/// Three-way merge of two rendered snapshot versions into a project.
+///
+/// `base` is the version the project was cloned from, `new` the version to apply. Changes
+/// between them are replayed on top of the project files, keeping local edits; when both
+/// sides touched the same lines the file is written with conflict markers, or for binary
+/// files and symlinks the new version is written next to it as `<file>.rej`. Modes and
+/// symlinks are carried over from `new` as a clone writes them.
+pub fn merge_tree(base: &Path, new: &Path, project: &Path) -> Result<UpdateReport> {
+    let mut report = UpdateReport::default();
+    let files = walk(base)?
+        .into_iter()
+        .chain(walk(new)?)
+        .filter(|(_, metadata)| !metadata.is_dir())
+        .map(|(file, _)| file)
+        .collect::<BTreeSet<_>>();
+
+    for file in files {
+        let name = file.display().to_string();
+        if name == ANSWERS_FILE {
+            continue;
+        }
+
+        let base_entry = read_optional(&base.join(&file))?;
+        let new_entry = read_optional(&new.join(&file))?;
+        let current_entry = read_optional(&project.join(&file))?;
+        let target = project.join(&file);
+
+        match (base_entry, new_entry, current_entry) {
+            (None, Some(new), None) => {
+                write_entry(&target, &new)?;
+                report.added.push(name);
+            }
+            (Some(base), Some(new), _) if base == new => {}
+            (Some(_), None, None) | (None, None, _) => {}
+            (Some(_), Some(_), None) => report.skipped.push(name),
+            (Some(base), None, Some(current)) => match base == current {
+                true => {
+                    fs::remove_file(&target)?;
+                    report.removed.push(name);
+                }
+                false => report.skipped.push(name),
+            },
+            (base, Some(new), Some(current)) => {
+                if current == new {
+                    continue;
+                }
+
+                if base.as_ref() == Some(&current) {
+                    write_entry(&target, &new)?;
+                    report.updated.push(name);
+                    continue;
+                }
+
+                let (
+                    Entry::File {
+                        content: current_content,
+                        permissions: current_permissions,
+                    },
+                    Entry::File {
+                        content: new_content,
+                        permissions: new_permissions,
+                    },
+                ) = (&current, &new)
+                else {
+                    // A symlink on either side can't be merged, keep the local one
+                    write_entry(&project.join(format!("{name}.rej")), &new)?;
+                    report.conflicts.push(name);
+                    continue;
+                };
+
+                // Lines mean nothing in binaries, keep the local file and put the new one aside
+                if is_binary(current_content) || is_binary(new_content) {
+                    write_entry(&project.join(format!("{name}.rej")), &new)?;
+                    report.conflicts.push(name);
+                    continue;
+                }
+
+                let (base_content, base_permissions) = match &base {
+                    Some(Entry::File {
+                        content,
+                        permissions,
+                    }) => (content.clone(), Some(permissions)),
+                    _ => (vec![], None),
+                };
+
+                // The mode follows the new version unless it was changed locally
+                let permissions = match base_permissions == Some(current_permissions) {
+                    true => new_permissions.clone(),
+                    false => current_permissions.clone(),
+                };
+
+                let (content, conflicted) =
+                    match diffy::merge_bytes(&base_content, current_content, new_content) {
+                        Ok(merged) => (merged, false),
+                        Err(conflicted) => (conflicted, true),
+                    };
+
+                write_entry(
+                    &target,
+                    &Entry::File {
+                        content,
+                        permissions,
+                    },
+                )?;
+                match conflicted {
+                    true => report.conflicts.push(name),
+                    false => report.updated.push(name),
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// The file or symlink at `path`, `None` when there is none or it is a folder
+fn read_optional(path: &Path) -> Result<Option<Entry>> {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Ok(None);
+    };
+
+    if metadata.is_symlink() {
+        Ok(Some(Entry::Symlink(fs::read_link(path)?)))
+    } else if metadata.is_file() {
+        Ok(Some(Entry::File {
+            content: fs::read(path)?,
+            permissions: metadata.permissions(),
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Replaces whatever is at `path`, never writing through a symlink
+fn write_entry(path: &Path, entry: &Entry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if path.symlink_metadata().is_ok() {
+        fs::remove_file(path)?;
+    }
+
+    match entry {
+        Entry::File {
+            content,
+            permissions,
+        } => {
+            fs::write(path, content)?;
+            fs::set_permissions(path, permissions.clone())?;
+        }
+        Entry::Symlink(target) => create_symlink(target, path)?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::core::testing::TestFolder;
+
+    use super::merge_tree;
+
+    fn tree(files: &[(&str, &str)]) -> TestFolder {
+        files
+            .iter()
+            .fold(TestFolder::new(), |folder, (name, content)| {
+                folder.add_file(name, content)
+            })
+    }
+
+    fn read(tree: &TestFolder, name: &str) -> Option<String> {
+        fs::read_to_string(tree.join(name)).ok()
+    }
+
+    #[test]
+    fn should_apply_snapshot_changes_keeping_local_edits() {
+        let base = tree(&[("ci.yml", "a\nb\nc\n"), ("old.txt", "old\n")]);
+        let new = tree(&[("ci.yml", "a\nb\nc\nd\n"), ("lint.toml", "lint\n")]);
+        let project = tree(&[("ci.yml", "A\nb\nc\n"), ("old.txt", "old\n")]);
+
+        let report = merge_tree(base.path(), new.path(), project.path()).unwrap();
+
+        assert_eq!(read(&project, "ci.yml").unwrap(), "A\nb\nc\nd\n");
+        assert_eq!(read(&project, "lint.toml").unwrap(), "lint\n");
+        assert_eq!(read(&project, "old.txt"), None);
+        assert_eq!(report.updated, vec!["ci.yml"]);
+        assert_eq!(report.added, vec!["lint.toml"]);
+        assert_eq!(report.removed, vec!["old.txt"]);
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn should_put_binary_conflicts_aside() {
+        let base = tree(&[("logo.png", "\0base")]);
+        let new = tree(&[("logo.png", "\0new")]);
+        let project = tree(&[("logo.png", "\0local")]);
+
+        let report = merge_tree(base.path(), new.path(), project.path()).unwrap();
+
+        assert_eq!(read(&project, "logo.png").unwrap(), "\0local");
+        assert_eq!(read(&project, "logo.png.rej").unwrap(), "\0new");
+        assert_eq!(report.conflicts, vec!["logo.png"]);
+    }
+
+    #[test]
+    fn should_leave_conflict_markers_when_edits_collide() {
+        let base = tree(&[("file", "a\nb\n")]);
+        let new = tree(&[("file", "a\nnew\n")]);
+        let project = tree(&[("file", "a\nlocal\n"), ("removed_upstream", "edited\n")]);
+        let base_with_removed = tree(&[("file", "a\nb\n"), ("removed_upstream", "x\n")]);
+
+        let report = merge_tree(base.path(), new.path(), project.path()).unwrap();
+        let merged = read(&project, "file").unwrap();
+
+        assert!(merged.contains("<<<<<<<") && merged.contains("local") && merged.contains("new"));
+        assert_eq!(report.conflicts, vec!["file"]);
+
+        let report = merge_tree(base_with_removed.path(), new.path(), project.path()).unwrap();
+
+        assert_eq!(report.skipped, vec!["removed_upstream"]);
+        assert!(read(&project, "removed_upstream").is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_carry_modes_and_symlinks_over() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let base = tree(&[("run.sh", "echo\n")]);
+        let new = tree(&[("run.sh", "echo\n"), ("target.txt", "new\n")]);
+        let project = tree(&[("run.sh", "echo\n")]);
+        let chmod = |tree: &TestFolder, mode| {
+            fs::set_permissions(tree.join("run.sh"), fs::Permissions::from_mode(mode)).unwrap()
+        };
+        chmod(&base, 0o644);
+        chmod(&new, 0o755);
+        chmod(&project, 0o644);
+        symlink("target.txt", new.join("latest")).unwrap();
+
+        let report = merge_tree(base.path(), new.path(), project.path()).unwrap();
+        let mode = fs::metadata(project.join("run.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        let link = fs::read_link(project.join("latest")).unwrap();
+
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(link, std::path::Path::new("target.txt"));
+        assert_eq!(report.updated, vec!["run.sh"]);
+        assert_eq!(report.added, vec!["latest", "target.txt"]);
+    }
+}
@@ -1,16 +1,23 @@
 use glob::Pattern;
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+use sha2::{Digest, Sha256};
 use std::{
+    collections::BTreeSet,
     fs,
-    io::{BufRead, Write},
-    path::Path,
-    sync::Mutex,
+    io::{BufRead, Read, Write},
+    path::{Component, Path, PathBuf},
 };
 
-use crate::error::Result;
-
-use super::Config;
+use crate::error::{Error, Result};
 
-static SETUPRSIGNORE: Mutex<Option<Vec<Pattern>>> = Mutex::new(None);
+use super::{
+    answers::{apply_computed, Answers},
+    template::Renderer,
+    Config, SetuprsConfig, Variables,
+};
 
 pub fn search_file_create_config_folder_if_not_found(
     folder_path_and_file: &str,
@@ -18,6 +25,7 @@ pub fn search_file_create_config_folder_if_not_found(
         snapshots_path,
         debug_mode,
         config_file_path,
+        ..
     }: &Config,
 ) -> Result<String> {
     let file_path = Path::new(folder_path_and_file);
@@ -65,188 +73,528 @@ where
     s.trim().to_string()
 }
 
-#[cfg(feature = "tui")]
-pub fn get_all_snapshot_ids(src: impl AsRef<Path>) -> Result<Vec<String>> {
-    let mut result: Vec<String> = vec![];
-    if let Ok(entries) = fs::read_dir(src) {
-        entries.for_each(|entry| {
-            if let Ok(entry) = entry {
-                if let Some(filename_str) = entry
-                    .path()
-                    .file_name()
-                    .and_then(|filename| filename.to_str())
-                {
-                    result.push(filename_str.to_string())
-                }
+/// Asks for a variable until the answer is valid for its type, failing once the input ends
+pub fn prompt_variable<R, W>(
+    mut reader: R,
+    mut writer: W,
+    variable: &Variables,
+) -> Result<toml::Value>
+where
+    R: BufRead,
+    W: Write,
+{
+    loop {
+        write!(&mut writer, "{}", variable.question())?;
+        writer.flush()?;
+
+        let mut s = String::new();
+        let read = reader.read_line(&mut s)?;
+
+        match variable.parse(&s) {
+            Ok(value) => return Ok(value),
+            Err(reason) if read == 0 => {
+                return Err(Error::InvalidAnswer {
+                    name: variable.name.clone(),
+                    reason,
+                })
             }
-        });
+            Err(reason) => writeln!(&mut writer, "Invalid value for {}: {reason}", variable.name)?,
+        }
     }
-    Ok(result)
 }
 
-fn is_ignored(path: &Path) -> bool {
-    let setup = SETUPRSIGNORE.lock().unwrap();
+/// Content hash of a folder, stable across machines since entries are visited by name.
+/// What its `.setuprsignore` files exclude is left out, as it never reaches a copy.
+pub fn hash_dir(src: impl AsRef<Path>) -> Result<String> {
+    let mut hasher = Sha256::new();
 
-    if let Some(ref ignore_patterns) = *setup {
-        if let Some(path_str) = path.to_str() {
-            for pattern in ignore_patterns.iter() {
-                if pattern.matches(path_str) {
-                    return true;
-                }
-            }
+    for (relative, metadata) in walk_unignored(src.as_ref())? {
+        let path = src.as_ref().join(&relative);
+
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update([0]);
+
+        if metadata.is_symlink() {
+            hasher.update(fs::read_link(&path)?.to_string_lossy().as_bytes());
+            hasher.update([0]);
+        } else if !metadata.is_dir() {
+            // Streamed, files are never loaded in memory whatever their size
+            std::io::copy(&mut fs::File::open(&path)?, &mut hasher)?;
+            hasher.update([0]);
         }
     }
-    false
+
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
-fn load_gitignore_patterns(path: &Path) -> Vec<Pattern> {
-    let path_setuprsignore = format!("{}/.setuprsignore", path.display());
-    let mut patterns = Vec::new();
-    if let Ok(lines) = fs::read_to_string(path_setuprsignore) {
-        for line in lines.lines() {
-            let trimmed = line.trim();
-            if !trimmed.is_empty() && !trimmed.starts_with('#') {
-                if let Ok(pattern) = Pattern::new(&format!("{}/{trimmed}", path.display())) {
-                    patterns.push(pattern);
-                }
+/// Every file, folder and symlink under `dir` with its metadata, relative to `dir`.
+/// Entries are sorted by name, folders before their content, and symlinks are never
+/// followed.
+pub fn walk(dir: &Path) -> Result<Vec<(PathBuf, fs::Metadata)>> {
+    let mut entries = vec![];
+    walk_into(dir, Path::new(""), None, &mut entries)?;
+
+    Ok(entries)
+}
+
+/// `walk` leaving out what the `.setuprsignore` files of the tree exclude, like a copy
+pub fn walk_unignored(dir: &Path) -> Result<Vec<(PathBuf, fs::Metadata)>> {
+    let mut entries = vec![];
+    walk_into(dir, Path::new(""), Some(&mut vec![]), &mut entries)?;
+
+    Ok(entries)
+}
+
+fn walk_into(
+    root: &Path,
+    relative: &Path,
+    mut ignore_rules: Option<&mut Vec<Gitignore>>,
+    entries: &mut Vec<(PathBuf, fs::Metadata)>,
+) -> Result<()> {
+    let dir = root.join(relative);
+    if let Some(ignore_rules) = ignore_rules.as_deref_mut() {
+        ignore_rules.push(load_gitignore_patterns(&dir, &[dir.join(".setuprsignore")]));
+    }
+
+    let mut children = fs::read_dir(&dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    children.sort_by_key(|entry| entry.file_name());
+
+    for child in children {
+        let relative = relative.join(child.file_name());
+        let metadata = fs::symlink_metadata(child.path())?;
+        let is_dir = metadata.is_dir();
+
+        if let Some(ignore_rules) = ignore_rules.as_deref() {
+            if is_ignored(ignore_rules, &child.path(), is_dir) {
+                continue;
             }
         }
+
+        entries.push((relative.clone(), metadata));
+        if is_dir {
+            walk_into(root, &relative, ignore_rules.as_deref_mut(), entries)?;
+        }
+    }
+
+    if let Some(ignore_rules) = ignore_rules {
+        ignore_rules.pop();
     }
 
-    patterns
+    Ok(())
 }
 
-fn set_value(new_value: Vec<Pattern>) {
-    let mut setup = SETUPRSIGNORE.lock().unwrap();
-    if setup.is_none() {
-        *setup = Some(new_value);
-    }
+/// Has a NUL byte in its first 8KB, the same heuristic git uses
+pub fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|byte| *byte == 0)
 }
 
-type FileModifier = Option<Box<dyn Fn(&mut String) -> String + 'static>>;
+/// Writes each non empty list of files under its title, as the reports print them
+pub fn write_sections(
+    f: &mut std::fmt::Formatter<'_>,
+    sections: &[(&str, &[String])],
+) -> std::fmt::Result {
+    for (title, files) in sections.iter().filter(|(_, files)| !files.is_empty()) {
+        writeln!(f, "{title}:")?;
+        for file in files.iter() {
+            writeln!(f, "  {file}")?;
+        }
+    }
 
-pub fn copy_dir_all(
-    src: impl AsRef<Path>,
-    dst: impl AsRef<Path>,
-    file_modifier: &FileModifier,
-) -> Result<String> {
-    fs::create_dir_all(&dst)?;
+    Ok(())
+}
 
-    for entry in fs::read_dir(&src)? {
-        let entry = entry?;
-        let ty = entry.file_type()?;
-        set_value(load_gitignore_patterns(src.as_ref()));
+/// Ids of every snapshot, sorted, including the ones created before versions were stored
+pub fn get_all_snapshot_ids(src: impl AsRef<Path>) -> Result<Vec<String>> {
+    let mut result = BTreeSet::new();
+    for dir in [src.as_ref().to_path_buf(), src.as_ref().join(".trees")] {
+        if let Ok(entries) = fs::read_dir(dir) {
+            entries.for_each(|entry| {
+                if let Ok(entry) = entry {
+                    if let Some(filename_str) = entry
+                        .path()
+                        .file_name()
+                        .and_then(|filename| filename.to_str())
+                        .filter(|filename| !filename.starts_with('.'))
+                    {
+                        result.insert(filename_str.to_string());
+                    }
+                }
+            });
+        }
+    }
+    Ok(result.into_iter().collect())
+}
 
-        if is_ignored(&entry.path()) {
-            continue;
+/// Checks `path` against the rules of every ignore file above it, the closest one
+/// deciding first, the same precedence git gives to nested `.gitignore` files
+fn is_ignored(ignore_rules: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    for rules in ignore_rules.iter().rev() {
+        match rules.matched(path, is_dir) {
+            Match::Ignore(_) => return true,
+            Match::Whitelist(_) => return false,
+            Match::None => {}
         }
+    }
+    false
+}
 
-        if ty.is_dir() {
-            copy_dir_all(
-                entry.path(),
-                dst.as_ref().join(entry.file_name()),
-                file_modifier,
-            )?;
-        } else {
-            let mut file_content = fs::read_to_string(entry.path())?;
-
-            if let Some(modifier) = file_modifier {
-                file_content = modifier(&mut file_content);
-            }
+/// Rules of the given ignore files, matched relative to `path` like a `.gitignore`.
+/// Later files take precedence over earlier ones.
+fn load_gitignore_patterns(path: &Path, ignore_files: &[PathBuf]) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(path);
+    for file in ignore_files {
+        // Lines that are not valid globs are skipped, the rest of the file still applies
+        let _ = builder.add(file);
+    }
 
-            let mut copied_file = fs::File::create(dst.as_ref().join(entry.file_name()))?;
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
 
-            copied_file.write_all(file_content.as_bytes())?;
-        }
-    }
-    Ok(dst.as_ref().display().to_string())
+pub type FileModifier = Option<Box<dyn Fn(&Path, &str) -> Result<String> + Send + Sync + 'static>>;
+
+/// What to do with symlinks pointing outside the copied tree, or using an absolute path.
+/// Links to entries inside the tree are always reproduced as links.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SymlinkPolicy {
+    /// Reproduce the link as it is, even if it may dangle elsewhere
+    #[default]
+    Keep,
+    /// Copy the file or folder the link points to
+    Follow,
+    /// Leave the link out
+    Skip,
 }
 
-#[cfg(test)]
-mod tests {
-    use std::{
-        fs::{self, File},
-        io::Write,
-        path::Path,
-    };
+#[derive(Clone, Debug, Default)]
+pub struct CopyOptions {
+    pub outside_symlinks: SymlinkPolicy,
+    pub preserve_mtime: bool,
+    /// Also skip what the `.gitignore` files and `.git/info/exclude` of the tree ignore
+    pub respect_gitignore: bool,
+    /// Only copy these files, relative to the copied tree
+    pub tracked_files: Option<BTreeSet<PathBuf>>,
+}
 
-    use glob::Pattern;
-    use serial_test::serial;
-    use uuid::Uuid;
+/// Copies folders leaving out what their `.setuprsignore` files exclude.
+///
+/// The ignore rules are loaded while walking and belong to that walk only, so the same
+/// copier can be used for any number of copies, from several threads at once.
+#[derive(Default)]
+pub struct Copier {
+    file_modifier: FileModifier,
+    binary_patterns: Vec<Pattern>,
+    options: CopyOptions,
+}
 
-    use crate::core::{
-        utils::{
-            copy_dir_all, get_all_snapshot_ids, is_ignored, load_gitignore_patterns,
-            search_file_create_config_folder_if_not_found,
-        },
-        Config,
-    };
+impl Copier {
+    pub fn new(options: CopyOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
 
-    use super::{get_input, SETUPRSIGNORE};
+    /// Renders file contents and names through `modifier`, except for binary files. It is
+    /// given the path of the file relative to the copied folder.
+    pub fn with_modifier<F>(mut self, modifier: F) -> Self
+    where
+        F: Fn(&Path, &str) -> Result<String> + Send + Sync + 'static,
+    {
+        self.file_modifier = Some(Box::new(modifier));
+        self
+    }
 
-    #[allow(dead_code)]
-    struct Noisy {
-        folder: String,
-        cleanup: Option<Box<dyn Fn()>>,
+    /// Files matching any of these, relative to the copied folder, are copied untouched
+    /// even with a modifier
+    pub fn with_binary_patterns(mut self, binary_patterns: Vec<Pattern>) -> Self {
+        self.binary_patterns = binary_patterns;
+        self
     }
 
-    #[allow(dead_code)]
-    struct NoisyFile {
-        name: String,
-        content: String,
+    pub fn copy(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<String> {
+        let mut ignore_rules = vec![];
+        let mut copying = vec![];
+        self.copy_dir(
+            src.as_ref(),
+            src.as_ref(),
+            dst.as_ref(),
+            &mut ignore_rules,
+            &mut copying,
+        )?;
+
+        Ok(dst.as_ref().display().to_string())
     }
 
-    impl Noisy {
-        fn new() -> Self {
-            let uuid = Uuid::new_v4().to_string();
-            fs::create_dir(&uuid).unwrap();
-            Self {
-                folder: uuid,
-                cleanup: None,
-            }
+    /// `copying` holds the canonical paths of `src` and the folders it is copied from, a
+    /// followed symlink leading back to one of them would never end
+    fn copy_dir(
+        &self,
+        root: &Path,
+        src: &Path,
+        dst: &Path,
+        ignore_rules: &mut Vec<Gitignore>,
+        copying: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        let Self {
+            file_modifier,
+            binary_patterns,
+            options,
+        } = self;
+
+        let canonical = fs::canonicalize(src)?;
+        if copying.contains(&canonical) {
+            return Err(Error::SymlinkCycle(
+                src.strip_prefix(root).unwrap_or(src).display().to_string(),
+            ));
         }
+        copying.push(canonical);
 
-        fn add_folder(self, folder_name: String) -> Self {
-            let path = format!("./{}/{}", self.folder, folder_name);
-            fs::create_dir(path).unwrap();
+        fs::create_dir_all(dst)?;
 
-            self
+        let mut ignore_files = vec![];
+        if options.respect_gitignore {
+            if src == root {
+                ignore_files.push(root.join(".git/info/exclude"));
+            }
+            ignore_files.push(src.join(".gitignore"));
         }
+        ignore_files.push(src.join(".setuprsignore"));
 
-        fn overwrite_cleanup(&mut self, closure: Box<dyn Fn()>) {
-            self.cleanup = Some(Box::new(closure));
-        }
+        ignore_rules.push(load_gitignore_patterns(src, &ignore_files));
 
-        fn add_file(self, noisy_file: NoisyFile) -> Self {
-            let path = format!("./{}/{}", self.folder, noisy_file.name);
-            let mut file = File::create(path).unwrap();
-            file.write_all(noisy_file.content.as_bytes()).unwrap();
-            self
-        }
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+
+            if is_ignored(ignore_rules, &entry.path(), entry.file_type()?.is_dir()) {
+                continue;
+            }
+
+            if options.respect_gitignore && entry.file_name() == ".git" {
+                continue;
+            }
+
+            if let Some(tracked_files) = &options.tracked_files {
+                if !is_tracked(root, &entry.path(), tracked_files) {
+                    continue;
+                }
+            }
+
+            let file_name = match (file_modifier, entry.file_name().to_str()) {
+                (Some(modifier), Some(name)) => modifier(relative, name)?.into(),
+                _ => entry.file_name(),
+            };
+
+            // A name rendered to nothing means the answers opted out of this entry
+            if file_name.is_empty() {
+                continue;
+            }
+
+            // Rendered names may add folders, never leave the one being copied to
+            if !Path::new(&file_name)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+            {
+                return Err(Error::TemplateError {
+                    file: relative.display().to_string(),
+                    line: 0,
+                    reason: format!(
+                        "name rendered to {}, outside the destination",
+                        Path::new(&file_name).display()
+                    ),
+                });
+            }
+
+            let target = dst.join(file_name);
+
+            if entry.file_type()?.is_symlink() {
+                let link = fs::read_link(entry.path())?;
+
+                match (
+                    points_inside(root, &entry.path(), &link),
+                    options.outside_symlinks,
+                ) {
+                    (true, _) | (false, SymlinkPolicy::Keep) if cfg!(unix) => {
+                        if let Some(parent) = target.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        if target.symlink_metadata().is_ok() {
+                            fs::remove_file(&target)?;
+                        }
+                        create_symlink(&link, &target)?;
+                        continue;
+                    }
+                    (false, SymlinkPolicy::Skip) => continue,
+                    // Followed, copied below as whatever it points to
+                    _ => {}
+                }
+            }
+
+            let metadata = fs::metadata(entry.path())?;
+
+            if metadata.is_dir() {
+                self.copy_dir(root, &entry.path(), &target, ignore_rules, copying)?;
+                // Applied once the content is in, a read only folder would refuse it otherwise
+                fs::set_permissions(&target, metadata.permissions())?;
+                if options.preserve_mtime {
+                    fs::File::open(&target)?.set_modified(metadata.modified()?)?;
+                }
+                continue;
+            }
 
-        fn add_file_non_method(folder: String, noisy_file: NoisyFile) {
-            let path = format!("./{}/{}", folder, noisy_file.name);
-            let mut file = File::create(path).unwrap();
-            file.write_all(noisy_file.content.as_bytes()).unwrap();
+            // Rendered names may expand into nested folders, e.g. `{{module_path}}.rs`
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            match file_modifier {
+                Some(modifier)
+                    if !binary_patterns
+                        .iter()
+                        .any(|pattern| pattern.matches_path(relative))
+                        && !is_binary_file(&path)? =>
+                {
+                    match String::from_utf8(fs::read(&path)?) {
+                        Ok(file_content) => {
+                            let file_content = modifier(relative, &file_content)?;
+                            let mut copied_file = fs::File::create(&target)?;
+
+                            copied_file.write_all(file_content.as_bytes())?;
+                        }
+                        // Not text after all, keep the bytes untouched
+                        Err(e) => fs::write(&target, e.into_bytes())?,
+                    }
+                    fs::set_permissions(&target, metadata.permissions())?;
+                }
+                // Streamed by the OS, nothing is loaded in memory, permissions included
+                _ => {
+                    fs::copy(entry.path(), &target)?;
+                }
+            }
+
+            if options.preserve_mtime {
+                fs::File::open(&target)?.set_modified(metadata.modified()?)?;
+            }
         }
+
+        ignore_rules.pop();
+        copying.pop();
+        Ok(())
     }
+}
 
-    impl Drop for Noisy {
-        fn drop(&mut self) {
-            let _ = fs::remove_dir_all(format!("./{}", self.folder));
+/// Tracked files and the folders holding them. The root `setuprs.toml` is always kept,
+/// a snapshot can't be cloned without it.
+fn is_tracked(root: &Path, path: &Path, tracked_files: &BTreeSet<PathBuf>) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
 
-            match &self.cleanup {
-                Some(f) => f(),
-                None => {}
-            }
+    relative == Path::new("setuprs.toml")
+        || tracked_files
+            .range(relative.to_path_buf()..)
+            .next()
+            .is_some_and(|file| file.starts_with(relative))
+}
+
+/// Whether a relative link stays within `root` once resolved from where it lives.
+/// Resolved by path only, so links to entries that don't exist yet are judged the same way.
+pub fn points_inside(root: &Path, link_path: &Path, link: &Path) -> bool {
+    if link.is_absolute() {
+        return false;
+    }
+
+    let location = link_path
+        .parent()
+        .and_then(|parent| parent.strip_prefix(root).ok())
+        .unwrap_or(Path::new(""));
+
+    let mut depth = 0usize;
+    for component in location.join(link).components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::ParentDir if depth == 0 => return false,
+            Component::ParentDir => depth -= 1,
+            _ => {}
         }
     }
 
-    fn set_value(new_value: Option<Vec<Pattern>>) {
-        let mut setup = SETUPRSIGNORE.lock().unwrap();
-        *setup = new_value;
+    true
+}
+
+#[cfg(unix)]
+pub fn create_symlink(original: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(not(unix))]
+pub fn create_symlink(_original: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Reads only what `is_binary` looks at, binary files are streamed when copied
+fn is_binary_file(path: &Path) -> Result<bool> {
+    let mut buffer = [0; 8000];
+    let read = fs::File::open(path)?.read(&mut buffer)?;
+
+    Ok(is_binary(&buffer[..read]))
+}
+
+/// Copies a snapshot rendering file contents and names with the given answers,
+/// plus the computed variables declared in its `setuprs.toml`.
+/// Files matching its `binary_globs` or `exclude_render` are copied as they are.
+pub fn render_snapshot(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    answers: &Answers,
+    options: &CopyOptions,
+) -> Result<String> {
+    let setuprs_config = SetuprsConfig::load(&src)?;
+    let computed = setuprs_config.computed.unwrap_or_default();
+    let answers = apply_computed(answers, &computed, Path::new("setuprs.toml"))?;
+
+    // The template declaration itself must reach the clone as written
+    let mut binary_patterns =
+        vec![Pattern::new("setuprs.toml").expect("a plain file name is always valid")];
+
+    for (key, globs) in [
+        ("binary_globs", setuprs_config.binary_globs),
+        ("exclude_render", setuprs_config.exclude_render),
+    ] {
+        for glob in globs.unwrap_or_default() {
+            binary_patterns.push(Pattern::new(&glob).map_err(|e| Error::InvalidConfigGlob {
+                key: key.to_string(),
+                glob,
+                reason: e.to_string(),
+            })?);
+        }
     }
 
+    let renderer = Renderer::new(&answers);
+
+    Copier::new(options.clone())
+        .with_modifier(move |file, content| renderer.render(file, content))
+        .with_binary_patterns(binary_patterns)
+        .copy(src, dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::core::{
+        answers::Answers,
+        testing::{test_config, TestFolder},
+        utils::{
+            hash_dir, is_ignored, load_gitignore_patterns, render_snapshot,
+            search_file_create_config_folder_if_not_found, Copier, CopyOptions, SymlinkPolicy,
+        },
+    };
+
+    use crate::core::utils::get_all_snapshot_ids;
+    use crate::error::Error;
+
+    use super::get_input;
+
     #[test]
     fn get_input_should_return_the_correct_input_when_called() {
         let input = b"I'm George";
@@ -259,166 +607,396 @@ mod tests {
     }
 
     #[test]
-    #[serial]
     fn should_return_true_when_file_is_on_ignore() {
-        let Noisy { folder, cleanup: _ } = &Noisy::new().add_file(NoisyFile {
-            name: ".setuprsignore".to_string(),
-            content: "ignored_file_0\nignored_file_1\nfolder/ignored_file_2".to_string(),
-        });
-
-        set_value(Some(load_gitignore_patterns(Path::new(folder))));
+        let folder = TestFolder::new().add_file(
+            ".setuprsignore",
+            "ignored_file_0\nignored_file_1\nfolder/ignored_file_2",
+        );
 
-        let on_folder = |file: &str| -> String { format!("{folder}/{file}") };
+        let rules = [load_gitignore_patterns(
+            folder.path(),
+            &[folder.join(".setuprsignore")],
+        )];
 
-        assert!(is_ignored(Path::new(&format!("{folder}/ignored_file_0"))));
-        assert!(is_ignored(Path::new(&on_folder("ignored_file_0"))));
-        assert!(is_ignored(Path::new(&on_folder("ignored_file_1"))));
-        assert!(is_ignored(Path::new(&on_folder("folder/ignored_file_2"))));
+        assert!(is_ignored(&rules, &folder.join("ignored_file_0"), false));
+        assert!(is_ignored(&rules, &folder.join("ignored_file_1"), false));
+        assert!(is_ignored(
+            &rules,
+            &folder.join("folder/ignored_file_2"),
+            false
+        ));
 
-        assert!(!is_ignored(Path::new("file_1")));
+        assert!(!is_ignored(&rules, Path::new("file_1"), false));
     }
 
     #[test]
-    #[serial]
     fn should_return_false_when_file_is_not_on_ignore() {
-        let Noisy { folder, cleanup: _ } = &Noisy::new().add_file(NoisyFile {
-            name: ".setuprsignore".to_string(),
-            content: "ignored_file_0\nignored_file_1\nfolder/ignored_file_2".to_string(),
-        });
+        let folder = TestFolder::new().add_file(
+            ".setuprsignore",
+            "ignored_file_0\nignored_file_1\nfolder/ignored_file_2",
+        );
+
+        let rules = [load_gitignore_patterns(
+            folder.path(),
+            &[folder.join(".setuprsignore")],
+        )];
+
+        assert!(!is_ignored(&rules, &folder.join("file_1"), false));
+        assert!(is_ignored(&rules, &folder.join("ignored_file_0"), false));
+    }
 
-        set_value(Some(load_gitignore_patterns(Path::new(folder))));
+    #[test]
+    fn should_follow_gitignore_rules_in_nested_ignore_files() {
+        let folder = [
+            "debug.log",
+            "keep.log",
+            "cache",
+            "build/out",
+            "src/build/mod.rs",
+            "src/build.log",
+            "src/local.rs",
+            "src/main.rs",
+            "src/generated/api.rs",
+            "src/generated/README.md",
+        ]
+        .into_iter()
+        .fold(TestFolder::new(), |folder, file| folder.add_file(file, ""))
+        .add_file(
+            ".setuprsignore",
+            "*.log\n!keep.log\n/build/\n**/generated/*.rs\ncache/",
+        )
+        .add_file("src/.setuprsignore", "local.rs\n!build.log");
+        let destination = TestFolder::new();
+
+        Copier::default()
+            .copy(folder.path(), destination.path())
+            .unwrap();
+
+        let copied = |file: &str| destination.join(file).exists();
+
+        assert!(!copied("debug.log"));
+        assert!(copied("keep.log"));
+        // `cache/` only applies to folders
+        assert!(copied("cache"));
+        // `/build/` is anchored to the folder of its ignore file
+        assert!(!copied("build"));
+        assert!(copied("src/build/mod.rs"));
+        assert!(copied("src/build.log"));
+        assert!(!copied("src/local.rs"));
+        assert!(copied("src/main.rs"));
+        assert!(!copied("src/generated/api.rs"));
+        assert!(copied("src/generated/README.md"));
+    }
 
-        let on_folder = |file: &str| -> String { format!("{folder}/{file}") };
-        assert!(!is_ignored(Path::new(&on_folder("file_1"))));
-        assert!(is_ignored(Path::new(&on_folder("ignored_file_0"))));
+    #[test]
+    fn should_copy_concurrently_with_independent_ignore_rules() {
+        let first = TestFolder::new()
+            .add_file(".setuprsignore", "a.txt")
+            .add_file("a.txt", "")
+            .add_file("b.txt", "");
+        let second = TestFolder::new()
+            .add_file(".setuprsignore", "b.txt")
+            .add_file("a.txt", "")
+            .add_file("b.txt", "");
+        let destinations = TestFolder::new();
+
+        let copier = Copier::default();
+        std::thread::scope(|scope| {
+            for (src, name) in [(&first, "first"), (&second, "second")] {
+                let copier = &copier;
+                let dst = destinations.join(name);
+                scope.spawn(move || copier.copy(src.path(), dst).unwrap());
+            }
+        });
+        // The rules of a copy are gone once it ends
+        copier
+            .copy(first.path(), destinations.join("again"))
+            .unwrap();
+
+        let copied = |file: &str| destinations.join(file).exists();
+
+        assert!(!copied("first/a.txt"));
+        assert!(copied("first/b.txt"));
+        assert!(copied("second/a.txt"));
+        assert!(!copied("second/b.txt"));
+        assert!(!copied("again/a.txt"));
+        assert!(copied("again/b.txt"));
     }
 
     #[test]
-    #[serial]
     fn should_create_folder_and_file() {
-        let Noisy { folder, cleanup: _ } = &Noisy::new();
+        let folder = TestFolder::new();
+        let config = test_config(folder.path());
+        let file = folder.join("file.toml");
+
+        search_file_create_config_folder_if_not_found(&file.display().to_string(), &config)
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(file).unwrap(),
+            format!(
+                "config_file_path = '.'\ndebug_mode = 'error'\nsnapshots_path = '{}'",
+                config.snapshots_path
+            )
+        );
+    }
+
+    #[test]
+    fn should_copy_folder_recurcivilly() {
+        let folder = TestFolder::new().with_config();
+        let destination = TestFolder::new();
 
-        let config = Config {
-            config_file_path: ".".to_string(),
-            debug_mode: "error".to_string(),
-            snapshots_path: ".".to_string(),
+        Copier::default()
+            .copy(folder.path(), destination.path())
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(destination.join("file.toml")).unwrap(),
+            fs::read_to_string(folder.join("file.toml")).unwrap()
+        );
+    }
+
+    #[test]
+    fn should_copy_folder_recurcivilly_ignoring_files_of_setuprsignore() {
+        let folder = TestFolder::new()
+            .add_file("normalfile", "")
+            .add_file("normalfile1", "")
+            .add_file("ignored_file_0", "")
+            .add_file("folder/ignored_file_1", "")
+            .add_file(".setuprsignore", "ignored_file_0\nfolder/ignored_file_1");
+        let destination = TestFolder::new();
+
+        Copier::default()
+            .copy(folder.path(), destination.path())
+            .unwrap();
+
+        assert!(destination.join("normalfile").exists());
+        assert!(!destination.join("ignored_file_0").exists());
+        assert!(!destination.join("folder/ignored_file_1").exists());
+    }
+
+    #[test]
+    fn should_render_file_and_folder_names_with_modifier() {
+        let folder = TestFolder::new()
+            .add_file("{{crate_name}}/lib.rs", "")
+            .add_file("{{project_name}}.service", "")
+            .add_file("{{optional}}", "")
+            .add_folder("{{optional}}_folder");
+        let destination = TestFolder::new();
+
+        let modifier = |_: &Path, content: &str| -> crate::error::Result<String> {
+            Ok(content
+                .replace("{{crate_name}}", "my_crate")
+                .replace("{{project_name}}", "my-project")
+                .replace("{{optional}}_folder", "")
+                .replace("{{optional}}", ""))
         };
 
-        let file = "file.toml".to_string();
+        Copier::default()
+            .with_modifier(modifier)
+            .copy(folder.path(), destination.path())
+            .unwrap();
+
+        assert!(destination.join("my_crate/lib.rs").exists());
+        assert!(destination.join("my-project.service").exists());
+        assert_eq!(fs::read_dir(destination.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn should_refuse_rendered_names_leaving_the_destination() {
+        let folder = TestFolder::new().add_file("{{dir}}", "hi");
+        let destination = TestFolder::new();
+
+        for name in ["../escaped.txt", "/escaped.txt", "sub/../../escaped.txt"] {
+            let copied = Copier::default()
+                .with_modifier(move |_, content| Ok(content.replace("{{dir}}", name)))
+                .copy(folder.path(), destination.join("copy"));
+
+            assert!(
+                matches!(copied, Err(Error::TemplateError { .. })),
+                "{name} was accepted"
+            );
+        }
+
+        assert!(!destination.join("escaped.txt").exists());
+    }
 
-        search_file_create_config_folder_if_not_found(
-            format!("./{folder}/{file}").as_str(),
-            &config,
+    #[test]
+    fn should_copy_binary_and_binary_globs_files_untouched() {
+        let folder = TestFolder::new()
+            .add_file(
+                "setuprs.toml",
+                "binary_globs = ['*.tmpl']\nexclude_render = ['.github/**']",
+            )
+            .add_file(".github/workflows/ci.yml", "runs-on: ${{ matrix.os }}")
+            .add_file("text.txt", "{{name}}")
+            .add_file("chart.tmpl", "{{ .Values.name }}")
+            .add_file("font.ttf", "\0{{name}}");
+        let destination = TestFolder::new();
+        fs::write(
+            folder.join("logo.png"),
+            [0x89, b'P', b'N', b'G', 0xff, 0xfe],
         )
         .unwrap();
 
-        let file: String = fs::read_to_string(format!("./{folder}/{file}")).unwrap();
+        let answers = Answers::from([("name".to_string(), toml::Value::String("app".into()))]);
+        render_snapshot(
+            folder.path(),
+            destination.path(),
+            &answers,
+            &CopyOptions::default(),
+        )
+        .unwrap();
+
+        let read = |file: &str| fs::read(destination.join(file)).unwrap();
+
+        assert_eq!(read("text.txt"), b"app");
+        assert_eq!(read("chart.tmpl"), b"{{ .Values.name }}");
         assert_eq!(
-            file,
-            "config_file_path = '.'\ndebug_mode = 'error'\nsnapshots_path = '.'"
+            read(".github/workflows/ci.yml"),
+            b"runs-on: ${{ matrix.os }}"
         );
+        assert_eq!(read("font.ttf"), b"\0{{name}}");
+        assert_eq!(read("logo.png"), [0x89, b'P', b'N', b'G', 0xff, 0xfe]);
     }
 
     #[test]
-    #[serial]
-    fn should_copy_folder_recurcivilly() {
-        let noisy = &mut Noisy::new();
-        let folder = &noisy.folder.clone();
-
-        set_value(None);
-        noisy.overwrite_cleanup(Box::new(move || {
-            fs::remove_dir_all("test_folder_copy").unwrap();
-        }));
-
-        let config = Config {
-            config_file_path: ".".to_string(),
-            debug_mode: "error".to_string(),
-            snapshots_path: ".".to_string(),
+    #[cfg(unix)]
+    fn should_keep_permissions_and_mtime_of_rendered_files() {
+        use std::{os::unix::fs::PermissionsExt, time::SystemTime};
+
+        let folder = TestFolder::new().add_file("scripts/bootstrap.sh", "echo {{name}}");
+        let destination = TestFolder::new();
+
+        let script = folder.join("scripts/bootstrap.sh");
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::File::open(&script)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let answers = Answers::from([("name".to_string(), toml::Value::String("app".into()))]);
+        let options = CopyOptions {
+            preserve_mtime: true,
+            ..Default::default()
         };
+        render_snapshot(folder.path(), destination.path(), &answers, &options).unwrap();
 
-        let file = "file.toml".to_string();
+        let copied = destination.join("scripts/bootstrap.sh");
+        let metadata = fs::metadata(&copied).unwrap();
 
-        search_file_create_config_folder_if_not_found(
-            format!("./{folder}/{file}").as_str(),
-            &config,
+        assert_eq!(fs::read_to_string(&copied).unwrap(), "echo app");
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        assert_eq!(metadata.modified().unwrap(), mtime);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn should_reproduce_symlinks_following_the_outside_policy() {
+        use std::os::unix::fs::symlink;
+
+        let outside = TestFolder::new().add_file("shared.txt", "shared");
+        let folder = TestFolder::new().add_file("docs/README.md", "readme");
+        let destinations = TestFolder::new();
+
+        let shared = fs::canonicalize(outside.join("shared.txt")).unwrap();
+        symlink("docs/README.md", folder.join("README.md")).unwrap();
+        symlink(&shared, folder.join("absolute.txt")).unwrap();
+        symlink(
+            Path::new("..").join(outside.join("shared.txt")),
+            folder.join("relative.txt"),
         )
         .unwrap();
 
-        copy_dir_all(folder, "./test_folder_copy", &None).unwrap();
+        let copy = |policy: SymlinkPolicy, destination: &str| {
+            let options = CopyOptions {
+                outside_symlinks: policy,
+                ..Default::default()
+            };
+            Copier::new(options)
+                .copy(folder.path(), destinations.join(destination))
+                .unwrap();
+        };
+
+        let is_link = |path: &str| {
+            fs::symlink_metadata(destinations.join(path))
+                .unwrap()
+                .is_symlink()
+        };
+
+        copy(SymlinkPolicy::Keep, "keep");
+        copy(SymlinkPolicy::Follow, "follow");
+        copy(SymlinkPolicy::Skip, "skip");
 
-        let file: String = fs::read_to_string(format!("./test_folder_copy/{file}")).unwrap();
         assert_eq!(
-            file,
-            "config_file_path = '.'\ndebug_mode = 'error'\nsnapshots_path = '.'"
+            fs::read_link(destinations.join("keep/README.md")).unwrap(),
+            Path::new("docs/README.md")
         );
+        assert_eq!(
+            fs::read_link(destinations.join("keep/absolute.txt")).unwrap(),
+            shared
+        );
+        assert_eq!(
+            fs::read_to_string(destinations.join("follow/relative.txt")).unwrap(),
+            "shared"
+        );
+        assert!(!is_link("follow/absolute.txt"));
+        assert!(is_link("skip/README.md"));
+        assert!(!destinations.join("skip/relative.txt").exists());
     }
 
     #[test]
-    #[serial]
-    fn should_copy_folder_recurcivilly_ignoring_files_of_setuprsignore() {
-        let noisy = &mut Noisy::new()
-            .add_file(NoisyFile {
-                name: "normalfile".to_string(),
-                content: "".to_string(),
-            })
-            .add_file(NoisyFile {
-                name: "normalfile1".to_string(),
-                content: "".to_string(),
-            })
-            .add_file(NoisyFile {
-                name: "ignored_file_0".to_string(),
-                content: "".to_string(),
-            })
-            .add_folder("folder".to_string())
-            .add_file(NoisyFile {
-                name: "folder/ignored_file_1".to_string(),
-                content: "".to_string(),
-            });
+    #[cfg(unix)]
+    fn should_refuse_to_follow_symlinks_to_a_folder_being_copied() {
+        let folder = TestFolder::new().add_file("project/main.rs", "fn main() {}");
+        let destination = TestFolder::new();
+        std::os::unix::fs::symlink("..", folder.join("project/up")).unwrap();
+
+        let options = CopyOptions {
+            outside_symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        let copied = Copier::new(options).copy(folder.join("project"), destination.join("copy"));
+
+        assert!(matches!(copied, Err(Error::SymlinkCycle(path)) if path.starts_with("up")));
+    }
 
-        let folder = &noisy.folder.clone();
-        Noisy::add_file_non_method(
-            folder.to_owned(),
-            NoisyFile {
-                name: ".setuprsignore".to_string(),
-                content: "ignored_file_0\nfolder/ignored_file_1".to_string(),
-            },
+    #[test]
+    fn should_hash_folder_by_content() {
+        let first = TestFolder::new().add_file("file", "content");
+        let second = TestFolder::new().add_file("file", "content");
+
+        assert_eq!(
+            hash_dir(first.path()).unwrap(),
+            hash_dir(second.path()).unwrap()
         );
 
-        noisy.overwrite_cleanup(Box::new(move || {
-            fs::remove_dir_all("test_folder_copy").unwrap();
-        }));
+        // Never copied, so never part of the content
+        let first = first.add_file(".setuprsignore", "*.log");
+        let second = second
+            .add_file(".setuprsignore", "*.log")
+            .add_file("debug.log", "noise");
 
-        set_value(None);
-        copy_dir_all(folder, "./test_folder_copy", &None).unwrap();
+        assert_eq!(
+            hash_dir(first.path()).unwrap(),
+            hash_dir(second.path()).unwrap()
+        );
 
-        let on_folder = |file: &str| -> String { format!("./test_folder_copy/{file}") };
+        let second = second.add_file("file", "changed");
 
-        assert!(Path::new(&on_folder("normalfile")).exists());
-        assert!(!Path::new(&on_folder("ignored_file_0")).exists());
-        assert!(!Path::new(&on_folder("folder/ignored_file_1")).exists());
+        assert_ne!(
+            hash_dir(first.path()).unwrap(),
+            hash_dir(second.path()).unwrap()
+        );
     }
 
     #[test]
-    #[serial]
     fn should_retrieve_id() {
-        let Noisy { folder, cleanup: _ } = &Noisy::new();
-
-        let config = Config {
-            config_file_path: ".".to_string(),
-            debug_mode: "error".to_string(),
-            snapshots_path: ".".to_string(),
-        };
-
-        let file = "file.toml".to_string();
-
-        search_file_create_config_folder_if_not_found(
-            format!("./{folder}/{file}").as_str(),
-            &config,
-        )
-        .unwrap();
+        let folder = TestFolder::new().with_config();
 
-        let result = get_all_snapshot_ids(folder).unwrap();
-        let expected = vec![format!("{file}")];
+        let result = get_all_snapshot_ids(folder.path()).unwrap();
+        let expected = vec!["file.toml".to_string()];
         assert_eq!(result, expected);
     }
 }
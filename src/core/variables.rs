@@ -0,0 +1,358 @@
+use regex::Regex;
+use serde_derive::Deserialize;
+use toml::Value;
+
+use crate::error::{Error, Result};
+
+#[derive(PartialEq, Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    #[default]
+    String,
+    Bool,
+    Int,
+    Choice,
+    List,
+}
+
+#[derive(PartialEq, Deserialize, Debug)]
+pub struct Variables {
+    pub name: String,
+    pub default: Option<Value>,
+    #[serde(rename = "type", default)]
+    pub kind: VariableType,
+    pub choices: Option<Vec<String>>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub pattern: Option<String>,
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub error_message: Option<String>,
+}
+
+impl Variables {
+    /// Checks the declaration itself, so a broken `setuprs.toml` fails before any prompt
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: String| Error::InvalidVariable {
+            name: self.name.clone(),
+            reason,
+        };
+
+        if self.kind == VariableType::Choice && self.choices.as_ref().is_none_or(Vec::is_empty) {
+            return Err(invalid(
+                "type \"choice\" requires a non empty `choices` list".into(),
+            ));
+        }
+
+        if let Some(pattern) = &self.pattern {
+            Regex::new(pattern).map_err(|e| invalid(format!("invalid pattern: {e}")))?;
+        }
+
+        self.default_value().map(|_| ()).map_err(invalid)
+    }
+
+    pub fn question(&self) -> String {
+        let hint = match self.kind {
+            VariableType::String => String::new(),
+            VariableType::Bool => " (yes/no)".to_string(),
+            VariableType::Int => format!(" ({})", self.range_hint()),
+            VariableType::Choice => {
+                format!(" ({})", self.choices.clone().unwrap_or_default().join("/"))
+            }
+            VariableType::List => " (comma separated)".to_string(),
+        };
+
+        match self.default_value() {
+            Ok(Some(default)) => format!(
+                "Enter value for {}{hint} [default: {}]: ",
+                self.name,
+                display_value(&default)
+            ),
+            _ => format!("Enter value for {}{hint}: ", self.name),
+        }
+    }
+
+    /// One line description for listings, e.g. `port: int 1..=65535 [default: 8080]`
+    pub fn summary(&self) -> String {
+        let kind = match (self.kind, self.min, self.max) {
+            (VariableType::Int, None, None) => "int".to_string(),
+            (VariableType::Int, _, _) => format!("int {}", self.range_hint()),
+            (VariableType::Choice, _, _) => format!(
+                "choice of {}",
+                self.choices.clone().unwrap_or_default().join("/")
+            ),
+            (kind, _, _) => kind.name().to_string(),
+        };
+
+        match self.default_value() {
+            Ok(Some(default)) => format!(
+                "{}: {kind} [default: {}]",
+                self.name,
+                display_value(&default)
+            ),
+            _ => format!("{}: {kind}", self.name),
+        }
+    }
+
+    /// Turns the raw prompt input into a typed value, falling back to the default when empty
+    pub fn parse(&self, input: &str) -> std::result::Result<Value, String> {
+        let input = input.trim();
+
+        if input.is_empty() {
+            return match self.default_value()? {
+                Some(default) => Ok(default),
+                None if self.kind == VariableType::String => self.parse_input(input),
+                None => Err("a value is required".to_string()),
+            };
+        }
+
+        self.parse_input(input)
+    }
+
+    pub fn default_value(&self) -> std::result::Result<Option<Value>, String> {
+        self.default
+            .as_ref()
+            .map(|default| self.coerce(default))
+            .transpose()
+    }
+
+    /// Checks an already typed value (a default or an answers file entry) against the declaration
+    pub fn coerce(&self, value: &Value) -> std::result::Result<Value, String> {
+        match (self.kind, value) {
+            (_, Value::String(s)) => self.parse_input(s.trim()),
+            (VariableType::Bool, Value::Boolean(_)) => Ok(value.clone()),
+            (VariableType::Int, Value::Integer(i)) => self.check_range(*i),
+            (VariableType::List, Value::Array(items)) => Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| match item {
+                        Value::String(s) => self.check_rules(s).map(|_| item.clone()),
+                        _ => Err(format!("list item {item} is not a string")),
+                    })
+                    .collect::<std::result::Result<_, _>>()?,
+            )),
+            _ => Err(format!(
+                "{value} does not match type \"{}\"",
+                self.kind.name()
+            )),
+        }
+    }
+
+    fn parse_input(&self, input: &str) -> std::result::Result<Value, String> {
+        match self.kind {
+            VariableType::String => {
+                self.check_rules(input)?;
+                Ok(Value::String(input.to_string()))
+            }
+            VariableType::Bool => match input.to_lowercase().as_str() {
+                "y" | "yes" | "true" => Ok(Value::Boolean(true)),
+                "n" | "no" | "false" => Ok(Value::Boolean(false)),
+                _ => Err(format!("expected yes or no, got \"{input}\"")),
+            },
+            VariableType::Int => match input.parse::<i64>() {
+                Ok(i) => self.check_range(i),
+                Err(_) => Err(format!("expected an integer, got \"{input}\"")),
+            },
+            VariableType::Choice => {
+                let choices = self.choices.clone().unwrap_or_default();
+                match choices.iter().any(|choice| choice == input) {
+                    true => {
+                        self.check_rules(input)?;
+                        Ok(Value::String(input.to_string()))
+                    }
+                    false => Err(format!(
+                        "expected one of {}, got \"{input}\"",
+                        choices.join(", ")
+                    )),
+                }
+            }
+            VariableType::List => Ok(Value::Array(
+                input
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| {
+                        self.check_rules(item)
+                            .map(|_| Value::String(item.to_string()))
+                    })
+                    .collect::<std::result::Result<_, _>>()?,
+            )),
+        }
+    }
+
+    fn check_range(&self, value: i64) -> std::result::Result<Value, String> {
+        if self.min.is_some_and(|min| value < min) || self.max.is_some_and(|max| value > max) {
+            return Err(format!("{value} is out of range ({})", self.range_hint()));
+        }
+
+        Ok(Value::Integer(value))
+    }
+
+    /// Applies `pattern`, `min_length` and `max_length`, reporting `error_message` when set
+    fn check_rules(&self, value: &str) -> std::result::Result<(), String> {
+        let length = value.chars().count();
+
+        let reason = match (self.min_length, self.max_length, &self.pattern) {
+            (Some(min), _, _) if length < min => format!("must have at least {min} characters"),
+            (_, Some(max), _) if length > max => format!("must have at most {max} characters"),
+            (_, _, Some(pattern)) => match Regex::new(pattern) {
+                Ok(regex) if regex.is_match(value) => return Ok(()),
+                Ok(_) => format!("\"{value}\" does not match {pattern}"),
+                Err(e) => format!("invalid pattern: {e}"),
+            },
+            _ => return Ok(()),
+        };
+
+        Err(self.error_message.clone().unwrap_or(reason))
+    }
+
+    fn range_hint(&self) -> String {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => format!("{min}..={max}"),
+            (Some(min), None) => format!(">= {min}"),
+            (None, Some(max)) => format!("<= {max}"),
+            (None, None) => "integer".to_string(),
+        }
+    }
+}
+
+impl VariableType {
+    fn name(&self) -> &'static str {
+        match self {
+            VariableType::String => "string",
+            VariableType::Bool => "bool",
+            VariableType::Int => "int",
+            VariableType::Choice => "choice",
+            VariableType::List => "list",
+        }
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Boolean(true) => "yes".to_string(),
+        Value::Boolean(false) => "no".to_string(),
+        Value::Array(items) => items
+            .iter()
+            .map(display_value)
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use super::Variables;
+
+    fn variable(declaration: &str) -> Variables {
+        toml::from_str(&format!("name = \"var\"\n{declaration}")).unwrap()
+    }
+
+    #[test]
+    fn should_keep_strings_as_typed() {
+        let var = variable("");
+
+        assert_eq!(var.parse(" value "), Ok(Value::String("value".into())));
+        assert_eq!(var.parse(""), Ok(Value::String("".into())));
+    }
+
+    #[test]
+    fn should_parse_bool_answers() {
+        let var = variable("type = \"bool\"\ndefault = true");
+
+        assert_eq!(var.parse("no"), Ok(Value::Boolean(false)));
+        assert_eq!(var.parse("Y"), Ok(Value::Boolean(true)));
+        assert_eq!(var.parse(""), Ok(Value::Boolean(true)));
+        assert!(var.parse("yse").is_err());
+    }
+
+    #[test]
+    fn should_reject_int_out_of_range() {
+        let var = variable("type = \"int\"\nmin = 1\nmax = 65535");
+
+        assert_eq!(var.parse("8080"), Ok(Value::Integer(8080)));
+        assert!(var.parse("80800").is_err());
+        assert!(var.parse("eighty").is_err());
+        assert!(var.parse("").is_err());
+    }
+
+    #[test]
+    fn should_only_accept_declared_choices() {
+        let var = variable("type = \"choice\"\nchoices = [\"mit\", \"apache\"]");
+
+        assert_eq!(var.parse("mit"), Ok(Value::String("mit".into())));
+        assert!(var.parse("gpl").is_err());
+    }
+
+    #[test]
+    fn should_split_lists_on_commas() {
+        let var = variable("type = \"list\"\ndefault = [\"api\"]");
+
+        assert_eq!(
+            var.parse("api, db,"),
+            Ok(Value::Array(vec!["api".into(), "db".into()]))
+        );
+        assert_eq!(var.parse(""), Ok(Value::Array(vec!["api".into()])));
+    }
+
+    #[test]
+    fn should_reject_answers_not_matching_pattern() {
+        let var = variable("pattern = \"^[a-z][a-z0-9_]*$\"");
+
+        assert_eq!(var.parse("my_crate"), Ok(Value::String("my_crate".into())));
+        assert_eq!(
+            var.parse("My-Crate"),
+            Err("\"My-Crate\" does not match ^[a-z][a-z0-9_]*$".to_string())
+        );
+        assert!(var.parse("").is_err());
+    }
+
+    #[test]
+    fn should_check_length_and_use_custom_message() {
+        let var =
+            variable("min_length = 3\nmax_length = 5\nerror_message = \"between 3 and 5 letters\"");
+
+        assert!(var.parse("abcd").is_ok());
+        assert_eq!(var.parse("ab"), Err("between 3 and 5 letters".to_string()));
+        assert_eq!(
+            var.parse("abcdef"),
+            Err("between 3 and 5 letters".to_string())
+        );
+    }
+
+    #[test]
+    fn should_summarize_declaration() {
+        assert_eq!(
+            variable("type = \"int\"\nmin = 1\nmax = 65535\ndefault = 8080").summary(),
+            "var: int 1..=65535 [default: 8080]"
+        );
+        assert_eq!(
+            variable("type = \"choice\"\nchoices = [\"mit\", \"apache\"]").summary(),
+            "var: choice of mit/apache"
+        );
+        assert_eq!(variable("").summary(), "var: string");
+    }
+
+    #[test]
+    fn should_fail_validation_when_pattern_is_invalid() {
+        assert!(variable("pattern = \"[a-z\"").validate().is_err());
+        assert!(variable("pattern = \"^a\"\ndefault = \"b\"")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn should_fail_validation_when_default_does_not_match_type() {
+        assert!(variable("type = \"int\"\ndefault = \"abc\"")
+            .validate()
+            .is_err());
+        assert!(variable("type = \"choice\"").validate().is_err());
+        assert!(variable("type = \"bool\"\ndefault = \"yes\"")
+            .validate()
+            .is_ok());
+    }
+}
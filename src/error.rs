@@ -5,10 +5,17 @@ use thiserror::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("{0}")]
     TomlError(#[from] toml::de::Error),
 
+    #[error("{0}")]
+    TomlSerializeError(#[from] toml::ser::Error),
+
+    #[error("{0}")]
+    JsonError(#[from] serde_json::Error),
+
     #[error("Missing setuprs init files, please run setuprs init")]
     MissingBasicInitialization,
 
@@ -17,6 +24,89 @@ pub enum Error {
 
     #[error("Provided snapshot don't exist")]
     SnapshotDontExist,
+
+    #[error("Invalid variable {name} in setuprs.toml: {reason}")]
+    InvalidVariable { name: String, reason: String },
+
+    #[error("Invalid value for {name}: {reason}")]
+    InvalidAnswer { name: String, reason: String },
+
+    #[error("Snapshot version {0} is not available anymore")]
+    SnapshotVersionDontExist(String),
+
+    #[error("{0} not found, only projects cloned by setuprs can be updated")]
+    MissingAnswersRecord(String),
+
+    #[error("Update finished with {0} conflicted file(s)")]
+    UpdateConflicts(usize),
+
+    #[error("{0} was cloned from a project folder, only projects cloned from a snapshot or a git repository can be updated")]
+    UnsupportedUpdateSource(String),
+
+    #[error("Invalid {key} entry {glob} in setuprs.toml: {reason}")]
+    InvalidConfigGlob {
+        key: String,
+        glob: String,
+        reason: String,
+    },
+
+    #[error("Missing values for variables without default: {0}")]
+    MissingAnswers(String),
+
+    #[error("Unknown variable {0}, it is not declared in setuprs.toml")]
+    UnknownVariable(String),
+
+    #[error("Failed to render template {file}:{line}: {reason}")]
+    TemplateError {
+        file: String,
+        line: usize,
+        reason: String,
+    },
+
+    #[error("{0} is not inside a git repository")]
+    NotAGitRepository(String),
+
+    #[error("Failed to read git index {path}: {reason}")]
+    InvalidGitIndex { path: String, reason: String },
+
+    #[error("Snapshot {0} already exists, use --force to replace it or --as to import it with another id")]
+    SnapshotAlreadyExists(String),
+
+    #[error("Invalid snapshot archive {path}: {reason}")]
+    InvalidArchive { path: String, reason: String },
+
+    #[error("Snapshot {0} was created by an older setuprs, create it again to export it")]
+    UnversionedSnapshot(String),
+
+    #[error("Can't clone from {reference}: {reason}")]
+    InvalidSource { reference: String, reason: String },
+
+    #[error("{0}")]
+    GitFailed(String),
+
+    #[error("Unknown registry {0}, it is not declared in the config file")]
+    UnknownRegistry(String),
+
+    #[error("Registry {name}: {reason}")]
+    RegistryError { name: String, reason: String },
+
+    #[error("{0} registries are configured, select one with --registry")]
+    AmbiguousRegistry(usize),
+
+    #[error("Invalid snapshot id {0}, it can't be empty, start with . or contain /, \\ or @")]
+    InvalidSnapshotId(String),
+
+    #[error("Invalid filter {glob}: {reason}")]
+    InvalidGlob { glob: String, reason: String },
+
+    #[error("Clone would overwrite modified files: {}. Nothing was written, choose what to do with --on-conflict", .0.join(", "))]
+    CloneConflict(Vec<String>),
+
+    #[error("Can't follow the symlinks of {0}, they lead back to a folder being copied")]
+    SymlinkCycle(String),
+
+    #[error("Snapshot {snapshot} is corrupted: {reason}")]
+    CorruptedSnapshot { snapshot: String, reason: String },
 }
 
 impl Debug for Error {
@@ -7,23 +7,33 @@ mod tui;
 use clap::Parser;
 use cli::{Cli, Commands, ConfigArgs, ConfigOptions, SnapshotArgs, SnapshotOptions};
 use core::{
-    utils::{copy_dir_all, get_input, search_file_create_config_folder_if_not_found},
-    Config, SetuprsConfig,
+    answers::{load_answers_file, resolve_answers, Answers, AnswersRecord, ANSWERS_FILE},
+    archive::{export, import, ImportOptions},
+    catalog::{list, Filter, OutputFormat, Table},
+    clone::install,
+    git::tracked_files,
+    manifest::SnapshotManifest,
+    preview::{diff_files, preview_clone},
+    source::Source,
+    store::{delete, duplicate, gc, rename, SnapshotDir, Tree},
+    update::merge_tree,
+    utils::{
+        get_input, hash_dir, prompt_variable, render_snapshot,
+        search_file_create_config_folder_if_not_found, Copier, CopyOptions,
+    },
+    validate_snapshot_id, Config, SetuprsConfig, Variables,
 };
 use error::*;
 
-#[cfg(feature = "tui")]
-use core::utils::get_all_snapshot_ids;
-
 #[cfg(feature = "tui")]
 use tui::app::{App, ObjList};
 
 use std::{
-    collections::HashMap,
     env,
     fs::{self, File},
     io::{self, Write},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 use uuid::Uuid;
 
@@ -46,124 +56,341 @@ async fn main() -> Result<()> {
 
     match &cli.command {
         Some(Commands::Snapshot(SnapshotArgs { command })) => match command {
-            SnapshotOptions::Show => {
-                let snapshots_path = &config.snapshots_path;
-
-                match fs::read_dir(snapshots_path) {
-                    Ok(e) => {
-                        let mut list: Vec<String> = e
-                            .filter_map(|e| {
-                                e.ok().and_then(|entry| {
-                                    entry.file_name().to_str().map(|s| s.to_string())
-                                })
-                            })
-                            .collect();
-
-                        list.sort();
-
-                        list.iter().for_each(|file| println!("{file}"));
+            SnapshotOptions::Show {
+                tags,
+                filter,
+                sort,
+                reverse,
+                format,
+            } => {
+                let exists = Path::new(&config.snapshots_path).exists();
+                if !exists && *format != OutputFormat::Json {
+                    println!("No snapshots on {}", config.snapshots_path);
+                    return Ok(());
+                }
+
+                let filter = Filter {
+                    tags: tags.clone(),
+                    glob: filter.clone(),
+                };
+                let summaries = match exists {
+                    true => list(&config, &filter, *sort, *reverse)?,
+                    false => vec![],
+                };
+
+                match format {
+                    OutputFormat::Table => print!("{}", Table(&summaries)),
+                    OutputFormat::Plain => summaries
+                        .iter()
+                        .for_each(|summary| println!("{}", summary.id)),
+                    OutputFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(&summaries)?)
                     }
-                    Err(_) => println!("No snapshots on {}", config.snapshots_path),
                 }
 
                 return Ok(());
             }
-            SnapshotOptions::Clone {
-                snapshot_id,
-                destination_path,
-            } => {
-                let snapshot_path = format!("{}{}", &config.snapshots_path, snapshot_id);
-                let destination_path = destination_path.clone().unwrap_or(".".to_string());
+            SnapshotOptions::Info { snapshot_id } => {
+                let (id, version) = config.resolve_snapshot(snapshot_id)?;
+                let snapshot = SnapshotDir::open(&config, &id, version, false)?;
+                let version = version.unwrap_or_default();
+
+                let manifest = match SnapshotManifest::load(&config, &id, version)? {
+                    Some(manifest) => manifest,
+                    None => SnapshotManifest::scan(&id, version, snapshot.path())?,
+                };
+
+                print!("{manifest}");
 
-                if !Path::new(&snapshot_path).exists() {
+                let variables = SetuprsConfig::load(snapshot.path())?
+                    .variables
+                    .unwrap_or_default();
+
+                if !variables.is_empty() {
+                    println!("Variables:");
+                    variables
+                        .iter()
+                        .for_each(|var| println!("  {}", var.summary()));
+                }
+            }
+            SnapshotOptions::Versions { snapshot_id } => {
+                let versions = config.versions(snapshot_id)?;
+                if versions.is_empty() && !config.snapshot_path(snapshot_id).exists() {
                     return Err(Error::SnapshotDontExist);
-                };
+                }
 
-                let setuprsconfig_path = format!("{}/setuprs.toml", snapshot_path);
+                let latest = versions.last().copied();
 
-                let variables = if Path::new(&setuprsconfig_path).exists() {
-                    let content = fs::read_to_string(&setuprsconfig_path)?;
-                    let setuprsconfig = toml::from_str::<SetuprsConfig>(&content)?;
-                    setuprsconfig.variables.unwrap_or_default()
-                } else {
-                    vec![]
-                };
+                for version in versions {
+                    let manifest = match SnapshotManifest::load(&config, snapshot_id, version)? {
+                        Some(manifest) => manifest,
+                        None => SnapshotManifest::scan(
+                            snapshot_id,
+                            version,
+                            SnapshotDir::open(&config, snapshot_id, Some(version), false)?.path(),
+                        )?,
+                    };
 
-                let mut answers_map: HashMap<String, String> = HashMap::new();
+                    println!(
+                        "{snapshot_id}@{version}  {}  {} files  {}{}",
+                        manifest.created_at.as_deref().unwrap_or("-"),
+                        manifest.file_count,
+                        manifest
+                            .content_hash
+                            .get(..12)
+                            .unwrap_or(&manifest.content_hash),
+                        if Some(version) == latest {
+                            "  (latest)"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+            SnapshotOptions::Delete { snapshot_id, yes } => {
+                if !config.snapshot_exists(snapshot_id)? {
+                    return Err(Error::SnapshotDontExist);
+                }
 
-                for var in variables {
-                    let stdio = io::stdin();
-                    let input = stdio.lock();
-                    let output = io::stdout();
+                if !yes {
+                    let versions = config.versions(snapshot_id)?.len();
+                    let answer = get_input(
+                        io::stdin().lock(),
+                        io::stdout(),
+                        &format!("Delete {snapshot_id} and its {versions} version(s)? [y/N] "),
+                    );
 
-                    let provided_value = match var.default {
-                        Some(default_value) => {
-                            let question = format!(
-                                "Enter value for {} [default: {}]: ",
-                                var.name, default_value
-                            );
+                    if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+                        println!("Aborted");
+                        return Ok(());
+                    }
+                }
 
-                            let input_value = get_input(input, output, &question);
+                delete(&config, snapshot_id)?;
+                println!("Deleted {snapshot_id}");
+            }
+            SnapshotOptions::Rename {
+                snapshot_id,
+                new_id,
+            } => {
+                rename(&config, snapshot_id, new_id)?;
+                println!("{new_id}");
+            }
+            SnapshotOptions::Duplicate {
+                snapshot_id,
+                new_id,
+            } => {
+                duplicate(&config, snapshot_id, new_id)?;
+                println!("{new_id}");
+            }
+            SnapshotOptions::Export {
+                snapshot_id,
+                output,
+            } => {
+                export(&config, snapshot_id, Path::new(output))?;
+                println!("Snapshot {snapshot_id} exported to {output}");
+            }
+            SnapshotOptions::Import {
+                archive,
+                force,
+                new_id,
+                allow_outside_symlinks,
+            } => {
+                let options = ImportOptions {
+                    new_id: new_id.as_deref(),
+                    force: *force,
+                    allow_outside_symlinks: *allow_outside_symlinks,
+                };
+                let id = import(&config, Path::new(archive), &options)?;
+                println!("{id}");
+            }
+            SnapshotOptions::Clone {
+                snapshot_id,
+                destination_path,
+                set,
+                answers,
+                defaults,
+                outside_symlinks,
+                preserve_mtime,
+                git_ref,
+                subdir,
+                dry_run,
+                diff,
+                on_conflict,
+            } => {
+                let source = Source::parse(snapshot_id);
+                let opened = source.open(&config, git_ref.as_deref(), *preserve_mtime)?;
 
-                            match input_value.trim().is_empty() {
-                                true => default_value,
-                                false => input_value.trim().to_string(),
-                            }
-                        }
+                let source_path = match subdir {
+                    Some(subdir) => opened.dir.path().join(subdir),
+                    None => opened.dir.path().to_path_buf(),
+                };
+                if !source_path.is_dir() {
+                    return Err(Error::InvalidSource {
+                        reference: snapshot_id.clone(),
+                        reason: format!("no {} folder", subdir.as_deref().unwrap_or_default()),
+                    });
+                }
 
-                        None => {
-                            let question = format!("Enter value for {}: ", var.name);
-                            get_input(input, output, &question)
-                        }
-                    };
+                let destination_path = destination_path.clone().unwrap_or(".".to_string());
 
-                    answers_map.insert(var.name, provided_value);
+                let variables = SetuprsConfig::load(&source_path)?
+                    .variables
+                    .unwrap_or_default();
+
+                for var in &variables {
+                    var.validate()?;
                 }
 
-                let modifier = move |s: &mut String| {
-                    let mut new_content = s.clone();
+                let mut provided = match answers {
+                    Some(path) => load_answers_file(path)?,
+                    None => Answers::new(),
+                };
 
-                    for (key, val) in answers_map.iter() {
-                        new_content = new_content.replace(&format!("{{{{{key}}}}}"), val);
+                for (key, value) in set {
+                    if !variables.iter().any(|var| &var.name == key) {
+                        return Err(Error::UnknownVariable(key.clone()));
                     }
+                    provided.insert(key.clone(), toml::Value::String(value.clone()));
+                }
+
+                let answers_map = resolve_answers(&variables, &provided, *defaults, prompt_stdin)?;
 
-                    new_content
+                let snapshot_hash = hash_dir(&source_path)?;
+
+                let options = CopyOptions {
+                    outside_symlinks: *outside_symlinks,
+                    preserve_mtime: *preserve_mtime,
+                    ..Default::default()
                 };
 
-                match copy_dir_all(snapshot_path, destination_path, &Some(Box::new(modifier))) {
-                    Ok(v) => {
-                        let path = fs::canonicalize(v)?;
-                        println!("Snapshot created in: {}", path.display());
-                        return Ok(());
+                // Rendered aside first, so conflicts are known before anything is written, a
+                // failed render leaves the destination untouched and the preview is what a
+                // clone writes
+                let rendered = SnapshotDir::temporary();
+                render_snapshot(&source_path, rendered.path(), &answers_map, &options)?;
+                AnswersRecord {
+                    snapshot_id: opened.id,
+                    snapshot_version: opened.version,
+                    snapshot_hash,
+                    source: Some(source.kind()),
+                    git_ref: git_ref.clone(),
+                    git_commit: opened.commit,
+                    subdir: subdir.clone(),
+                    answers: answers_map,
+                }
+                .write(rendered.path())?;
+
+                let destination = Path::new(&destination_path);
+
+                if *dry_run {
+                    let preview = preview_clone(rendered.path(), destination)?;
+                    print!("{preview}");
+                    if *diff {
+                        print!(
+                            "{}",
+                            diff_files(rendered.path(), destination, &preview.overwritten)?
+                        );
                     }
-                    Err(e) => return Err(e),
-                };
+
+                    return Ok(());
+                }
+
+                let report = install(rendered.path(), destination, *on_conflict, |file| {
+                    let answer = get_input(
+                        io::stdin().lock(),
+                        io::stdout(),
+                        &format!("Overwrite {file}? [y/N] "),
+                    );
+                    matches!(answer.to_lowercase().as_str(), "y" | "yes")
+                })?;
+                print!("{report}");
+
+                let path = fs::canonicalize(destination)?;
+                println!("Snapshot created in: {}", path.display());
+                return Ok(());
             }
 
-            SnapshotOptions::Create { project_path, name } => {
+            SnapshotOptions::Create {
+                project_path,
+                name,
+                outside_symlinks,
+                preserve_mtime,
+                respect_gitignore,
+                git_tracked_only,
+                description,
+                author,
+                tags,
+            } => {
                 if !Path::new(&format!("{project_path}/setuprs.toml")).exists() {
                     return Err(Error::MissingBasicInitialization);
                 };
 
-                let id = match name {
-                    Some(tag_value) => tag_value.to_string(),
-                    None => {
-                        let setuprs_config_file_path = format!("{project_path}/setuprs.toml");
-                        let content = fs::read_to_string(setuprs_config_file_path)?;
-                        let setuprs_config_data = toml::from_str::<SetuprsConfig>(&content)?;
+                let project = SetuprsConfig::load(project_path)?.project;
 
-                        match setuprs_config_data.project {
-                            Some(project) => project.name,
-                            _ => Uuid::new_v4().to_string(),
-                        }
-                    }
+                let id = match (name, &project) {
+                    (Some(tag_value), _) => tag_value.to_string(),
+                    (None, Some(project)) => project.name.clone(),
+                    (None, None) => Uuid::new_v4().to_string(),
+                };
+                validate_snapshot_id(&id)?;
+
+                let tracked_files = match git_tracked_only {
+                    true => Some(tracked_files(Path::new(project_path))?),
+                    false => None,
+                };
+
+                // Copied aside first so only what passes the ignore rules gets stored
+                let staging =
+                    Path::new(&config.snapshots_path).join(format!(".staging-{}", Uuid::new_v4()));
+
+                let stored = Copier::new(CopyOptions {
+                    outside_symlinks: *outside_symlinks,
+                    preserve_mtime: *preserve_mtime,
+                    respect_gitignore: *respect_gitignore,
+                    tracked_files,
+                })
+                .copy(project_path, &staging)
+                .and_then(|_| {
+                    Ok((
+                        store_version(&config, &id, &staging)?,
+                        SnapshotManifest::scan(&id, 0, &staging)?,
+                    ))
+                });
+                let _ = fs::remove_dir_all(&staging);
+
+                let (Some(version), scanned) = stored? else {
+                    println!("{}", id);
+                    return Ok(());
+                };
+
+                // Snapshots created before versions were stored are replaced by the store
+                let snapshot_path = config.snapshot_path(&id);
+                if snapshot_path.exists() {
+                    fs::remove_dir_all(&snapshot_path)?;
+                }
+
+                let (project_description, project_author, project_tags) = match project {
+                    Some(project) => (project.description, project.author, project.tags),
+                    None => (None, None, None),
                 };
 
-                copy_dir_all(
-                    project_path,
-                    format!("{}/{}", &config.snapshots_path, id),
-                    &None,
-                )?;
+                SnapshotManifest {
+                    created_at: Some(
+                        humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+                    ),
+                    source_path: Some(fs::canonicalize(project_path)?.display().to_string()),
+                    description: description.clone().or(project_description),
+                    author: author.clone().or(project_author),
+                    tags: match tags.is_empty() {
+                        true => project_tags.unwrap_or_default(),
+                        false => tags.clone(),
+                    },
+                    setuprs_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                    version,
+                    ..scanned
+                }
+                .write(&config)?;
 
                 println!("{}", id);
             }
@@ -214,9 +441,172 @@ name = \"{project_name}\"\n
                 .expect("Failed to write on setuprs.toml file");
         }
 
+        Some(Commands::Update { dir, defaults }) => {
+            let project_path = PathBuf::from(dir.clone().unwrap_or(".".to_string()));
+            let answers_path = project_path.join(ANSWERS_FILE);
+
+            if !answers_path.exists() {
+                return Err(Error::MissingAnswersRecord(
+                    answers_path.display().to_string(),
+                ));
+            }
+
+            let record = toml::from_str::<AnswersRecord>(&fs::read_to_string(&answers_path)?)?;
+
+            // Records written before the source was recorded tell it by their reference
+            let source = match record.source {
+                Some(kind) => Source::recorded(kind, &record.snapshot_id),
+                None => Source::parse(&record.snapshot_id),
+            };
+            if let Source::Dir(_) = source {
+                return Err(Error::UnsupportedUpdateSource(
+                    project_path.display().to_string(),
+                ));
+            }
+
+            let in_subdir = |dir: &SnapshotDir| match &record.subdir {
+                Some(subdir) => dir.path().join(subdir),
+                None => dir.path().to_path_buf(),
+            };
+
+            let opened = source.open(&config, record.git_ref.as_deref(), false)?;
+            let snapshot = in_subdir(&opened.dir);
+
+            let snapshot_hash = hash_dir(&snapshot)?;
+            if snapshot_hash == record.snapshot_hash {
+                println!("Already up to date");
+                return Ok(());
+            }
+
+            // Records written before versioning only know the content of their version
+            let base = match (&source, record.snapshot_version, &record.git_commit) {
+                (Source::Git(_), _, Some(commit)) => source.open(&config, Some(commit), false)?.dir,
+                (Source::Git(url), _, None) => {
+                    return Err(Error::InvalidSource {
+                        reference: url.clone(),
+                        reason: "the commit it was cloned from is not recorded".to_string(),
+                    })
+                }
+                (_, Some(version), _) => {
+                    SnapshotDir::open(&config, &record.snapshot_id, Some(version), false)?
+                }
+                (_, None, _) => config
+                    .versions(&record.snapshot_id)?
+                    .into_iter()
+                    .filter_map(|version| {
+                        SnapshotDir::open(&config, &record.snapshot_id, Some(version), false).ok()
+                    })
+                    .find(|base| {
+                        hash_dir(in_subdir(base)).is_ok_and(|hash| hash == record.snapshot_hash)
+                    })
+                    .ok_or_else(|| Error::SnapshotVersionDontExist(record.snapshot_hash.clone()))?,
+            };
+
+            let variables = SetuprsConfig::load(&snapshot)?
+                .variables
+                .unwrap_or_default();
+
+            for var in &variables {
+                var.validate()?;
+            }
+
+            let answers_map =
+                resolve_answers(&variables, &record.answers, *defaults, prompt_stdin)?;
+
+            let workdir = env::temp_dir().join(format!("setuprs-update-{}", Uuid::new_v4()));
+            let options = CopyOptions::default();
+            let report = render_snapshot(
+                in_subdir(&base),
+                workdir.join("base"),
+                &record.answers,
+                &options,
+            )
+            .and_then(|_| render_snapshot(&snapshot, workdir.join("new"), &answers_map, &options))
+            .and_then(|_| merge_tree(&workdir.join("base"), &workdir.join("new"), &project_path));
+            let _ = fs::remove_dir_all(&workdir);
+            let report = report?;
+
+            AnswersRecord {
+                snapshot_id: record.snapshot_id,
+                snapshot_version: opened.version,
+                snapshot_hash,
+                source: Some(source.kind()),
+                git_ref: record.git_ref,
+                git_commit: opened.commit,
+                subdir: record.subdir,
+                answers: answers_map,
+            }
+            .write(&project_path)?;
+
+            print!("{report}");
+
+            if !report.conflicts.is_empty() {
+                return Err(Error::UpdateConflicts(report.conflicts.len()));
+            }
+        }
+
+        Some(Commands::Gc) => {
+            print!("{}", gc(&config)?);
+        }
+
+        Some(Commands::Search { term }) => {
+            for registry in &config.registries {
+                match registry.index() {
+                    Ok(index) => index
+                        .snapshots
+                        .iter()
+                        .filter(|entry| entry.matches(term))
+                        .for_each(|entry| println!("{}/{entry}", registry.name)),
+                    Err(err) => eprintln!("Error: {}", err),
+                }
+            }
+        }
+
+        Some(Commands::Pull {
+            reference,
+            force,
+            new_id,
+            allow_outside_symlinks,
+        }) => {
+            let Some((registry, id)) = reference.split_once('/') else {
+                return Err(Error::RegistryError {
+                    name: reference.clone(),
+                    reason: "pull expects registry/id".to_string(),
+                });
+            };
+
+            let options = ImportOptions {
+                new_id: new_id.as_deref(),
+                force: *force,
+                allow_outside_symlinks: *allow_outside_symlinks,
+            };
+            let id = config.registry(registry)?.pull(&config, id, &options)?;
+            println!("{id}");
+        }
+
+        Some(Commands::Publish {
+            snapshot_id,
+            registry,
+        }) => {
+            let registry = match (registry, config.registries.as_slice()) {
+                (Some(name), _) => config.registry(name)?,
+                (None, [registry]) => registry,
+                (None, registries) => return Err(Error::AmbiguousRegistry(registries.len())),
+            };
+
+            let entry = registry.publish(&config, snapshot_id)?;
+            println!(
+                "Published {}@{} to {}",
+                entry.id, entry.version, registry.name
+            );
+        }
+
         #[cfg(feature = "tui")]
         Some(Commands::Tui {}) => {
-            let items_ids = get_all_snapshot_ids(&config.snapshots_path)?;
+            let items_ids = list(&config, &Filter::default(), Default::default(), false)?
+                .into_iter()
+                .map(|summary| summary.id)
+                .collect();
             let items = ObjList::from_array(items_ids);
             let mut app = App::new(items, config)?;
             app.run().await?;
@@ -227,3 +617,29 @@ name = \"{project_name}\"\n
 
     Ok(())
 }
+
+/// Stores the copy of a project as a new version of the snapshot `id`, `None` when it
+/// doesn't differ from the latest one
+fn store_version(config: &Config, id: &str, dir: &Path) -> Result<Option<u32>> {
+    let latest = config.versions(id)?.last().copied();
+    let tree = Tree::scan(dir)?;
+
+    if let Some(latest) = latest {
+        if Tree::load(config, id, latest)?.same_content(&tree) {
+            return Ok(None);
+        }
+    }
+
+    let version = latest.unwrap_or_default() + 1;
+    tree.write(config, id, version, dir)?;
+
+    Ok(Some(version))
+}
+
+fn prompt_stdin(variable: &Variables) -> Result<toml::Value> {
+    let stdio = io::stdin();
+    let input = stdio.lock();
+    let output = io::stdout();
+
+    prompt_variable(input, output, variable)
+}
@@ -1,7 +1,13 @@
+use std::path::Path;
+
 use crossterm::event::KeyCode;
 
 use crate::{
-    core::utils::copy_dir_all,
+    core::{
+        clone::{install, ConflictPolicy},
+        store::SnapshotDir,
+        utils::Copier,
+    },
     tui::app::{App, CurrentMode, DefaultActions},
 };
 
@@ -33,12 +39,24 @@ impl<'a> Confirming<'a> {
             }
             KeyCode::Enter => {
                 if let Some(selected_snapshot) = app.get_selected() {
-                    let snapshot_path = format!(
-                        "{}{}",
-                        app.current_config.snapshots_path, selected_snapshot.id
-                    );
+                    let config = &app.current_config;
+                    let copied = config
+                        .resolve_snapshot(&selected_snapshot.id)
+                        .and_then(|(id, version)| SnapshotDir::open(config, &id, version, false))
+                        .and_then(|snapshot| {
+                            // Copied aside first, a failed copy leaves the destination untouched
+                            let staging = SnapshotDir::temporary();
+                            Copier::default().copy(snapshot.path(), staging.path())?;
+
+                            install(
+                                staging.path(),
+                                Path::new(&app.copy_dir_input),
+                                ConflictPolicy::default(),
+                                |_| false,
+                            )
+                        });
 
-                    match copy_dir_all(snapshot_path, app.copy_dir_input.clone(), &None) {
+                    match copied {
                         Ok(_) => {
                             app.mode = CurrentMode::Exiting;
                         }